@@ -0,0 +1,116 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Restoring the windows of an app starting with 50 windows, on Tauri's mock runtime,
+//! run with `cargo bench --features testing`.
+//!
+//! The mock runtime reports no monitors and answers window calls right away,
+//! so this measures the plugin rather than the platform.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use tauri::{
+    test::{mock_builder, mock_context, noop_assets, MockRuntime},
+    App, WindowBuilder, WindowUrl,
+};
+use tauri_plugin_window_state::{
+    testing::seed_state, AppHandleExt, Builder, Result, StateFlags, StateStore, WindowState,
+};
+
+use std::{collections::HashMap, sync::Mutex};
+
+const WINDOWS: usize = 50;
+
+/// Keeps the state in memory, so benchmarks don't touch the disk.
+#[derive(Default)]
+struct MemoryStore(Mutex<Option<Vec<u8>>>);
+
+impl StateStore for MemoryStore {
+    fn load(&self) -> Result<Option<Vec<u8>>> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+
+    fn save(&self, bytes: &[u8]) -> Result<()> {
+        *self.0.lock().unwrap() = Some(bytes.to_vec());
+        Ok(())
+    }
+}
+
+/// An app running the plugin configured by `plugin`, with the stored state of 50 windows.
+fn app(plugin: Builder) -> App<MockRuntime> {
+    let app = mock_builder()
+        .plugin(plugin.with_store(MemoryStore::default()).build())
+        .build(mock_context(noop_assets()))
+        .unwrap();
+    seed_state(&app, states());
+    app
+}
+
+fn states() -> HashMap<String, WindowState> {
+    (0..WINDOWS)
+        .map(|i| {
+            let state = WindowState {
+                x: (i % 10) as i32 * 300,
+                y: (i / 10) as i32 * 200,
+                width: 800.,
+                height: 600.,
+                ..Default::default()
+            };
+            (format!("window-{}", i), state)
+        })
+        .collect()
+}
+
+/// All windows being created at once, each restoring on its own thread once its webview is ready.
+fn initial_restore(c: &mut Criterion) {
+    c.bench_function("initial restore of 50 windows", |b| {
+        b.iter_batched(
+            || app(Builder::default()),
+            |app| {
+                let handle = app.handle();
+                std::thread::scope(|s| {
+                    for label in states().into_keys() {
+                        let handle = &handle;
+                        s.spawn(move || {
+                            WindowBuilder::new(handle, label, WindowUrl::default())
+                                .build()
+                                .unwrap();
+                        });
+                    }
+                });
+                app
+            },
+            BatchSize::PerIteration,
+        )
+    });
+}
+
+/// [`AppHandleExt::restore_all_windows`] with and without [`Builder::with_parallel_restore`],
+/// called off the thread the app was built on, which the parallel restore runs sequentially on.
+///
+/// [`AppHandleExt::restore_all_windows`]: tauri_plugin_window_state::AppHandleExt::restore_all_windows
+fn restore_all_windows(c: &mut Criterion) {
+    let flags = StateFlags::SIZE | StateFlags::POSITION;
+
+    let mut group = c.benchmark_group("restore all of 50 windows");
+    for (name, parallel) in [("sequential", false), ("parallel", true)] {
+        let app = app(Builder::default().with_parallel_restore(parallel));
+        for label in states().into_keys() {
+            WindowBuilder::new(&app, label, WindowUrl::default())
+                .build()
+                .unwrap();
+        }
+        let handle = app.handle();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                std::thread::scope(|s| {
+                    s.spawn(|| handle.restore_all_windows(flags).unwrap());
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, initial_restore, restore_all_windows);
+criterion_main!(benches);
@@ -8,7 +8,18 @@ export enum StateFlags {
   VISIBLE = 1 << 3,
   DECORATIONS = 1 << 4,
   FULLSCREEN = 1 << 5,
-  ALL = SIZE | POSITION | MAXIMIZED | VISIBLE | DECORATIONS | FULLSCREEN,
+  SNAP = 1 << 6,
+  THEME = 1 << 7,
+  ZOOM = 1 << 8,
+  ALL = SIZE |
+    POSITION |
+    MAXIMIZED |
+    VISIBLE |
+    DECORATIONS |
+    FULLSCREEN |
+    SNAP |
+    THEME |
+    ZOOM,
 }
 
 /**
@@ -20,19 +31,135 @@ async function saveWindowState(flags: StateFlags): Promise<void> {
 
 /**
  *  Restore the state for the specified window from disk.
+ *
+ *  The window is only shown if `autoShow` is `true`, which defaults to the
+ *  plugin's auto show setting.
  */
 async function restoreState(
   label: WindowLabel,
   flags: StateFlags,
+  autoShow?: boolean,
 ): Promise<void> {
-  return invoke("plugin:window-state|restore_state", { label, flags });
+  return invoke("plugin:window-state|restore_state", {
+    label,
+    flags,
+    autoShow,
+  });
 }
 
 /**
  *  Restore the state for the current window from disk.
  */
-async function restoreStateCurrent(flags: StateFlags): Promise<void> {
-  return restoreState(getCurrent().label, flags);
+async function restoreStateCurrent(
+  flags: StateFlags,
+  autoShow?: boolean,
+): Promise<void> {
+  return restoreState(getCurrent().label, flags, autoShow);
+}
+
+/**
+ *  Forget the stored state of all windows and save the empty state to disk.
+ *  Takes effect on the next restore or launch.
+ *
+ *  The confirmation argument guards against wiring this up by accident.
+ */
+async function clearAllWindowStates(
+  confirmation: "clear-all-window-states",
+): Promise<void> {
+  return invoke("plugin:window-state|clear_all_window_states", {
+    confirmation,
+  });
+}
+
+/**
+ *  Remember the current state of all open windows without saving it to disk,
+ *  for example from a "remember this layout" hotkey.
+ *
+ *  Resolves to the number of windows captured. Call `commitLayout` to save it.
+ */
+async function captureCurrentLayout(): Promise<number> {
+  return invoke("plugin:window-state|capture_current_layout");
+}
+
+/**
+ *  Save the remembered window states to disk as they are.
+ */
+async function commitLayout(): Promise<void> {
+  return invoke("plugin:window-state|commit_layout");
+}
+
+/**
+ *  The stored state of a window, as returned by `getAllWindowStates`.
+ *
+ *  Sizes are logical, positions physical pixels. Fields beyond the geometry
+ *  are included as the plugin stores them.
+ */
+interface WindowState {
+  width: number;
+  height: number;
+  x: number;
+  y: number;
+  prev_x: number;
+  prev_y: number;
+  maximized: boolean;
+  visible: boolean;
+  decorated: boolean;
+  fullscreen: "none" | "borderless" | "exclusive";
+  [field: string]: unknown;
+}
+
+/**
+ *  Get the stored state of every window by label, for example to list the
+ *  remembered windows in a settings screen.
+ *
+ *  ```ts
+ *  const states = await getAllWindowStates();
+ *  for (const [label, state] of Object.entries(states)) {
+ *    console.log(label, state.width, state.height);
+ *  }
+ *  ```
+ */
+async function getAllWindowStates(): Promise<Record<string, WindowState>> {
+  return invoke("plugin:window-state|get_all_window_states");
+}
+
+/**
+ *  The active plugin configuration, as returned by `getConfig`.
+ */
+interface WindowStateConfig {
+  stateFlags: StateFlags;
+  proportionalRemap: boolean;
+  defaultTiling: "grid" | "columns" | null;
+  primaryRelativeCoords: boolean;
+  fitBeforeMaximize: boolean;
+  /** Whether restores show windows that were visible. */
+  autoShow: boolean;
+  /** Whether the state is saved to a custom store, whose details aren't reported. */
+  customStore: boolean;
+  /** The file the state is saved to, if any. */
+  path: string | null;
+  /** The file name of `path`, if any. */
+  filename: string | null;
+  /** The format the state is written in. */
+  format: "bincode" | "json";
+}
+
+/**
+ *  Get the active plugin configuration, for example to show it in a support overlay.
+ */
+async function getConfig(): Promise<WindowStateConfig> {
+  return invoke("plugin:window-state|get_config");
 }
 
-export { restoreState, restoreStateCurrent, saveWindowState };
+export type { WindowState, WindowStateConfig };
+
+export {
+  restoreState,
+  restoreStateCurrent,
+  saveWindowState,
+  clearAllWindowStates,
+  captureCurrentLayout,
+  commitLayout,
+  getAllWindowStates,
+  getConfig,
+};
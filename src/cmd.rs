@@ -1,4 +1,9 @@
-use crate::{AppHandleExt, StateFlags, WindowExt};
+use crate::{
+    AppHandleExt, PluginConfig, StateFlags, StateFormat, Store, TilingMode, WindowExtInternal,
+    WindowState,
+};
+use serde::Serialize;
+use std::{collections::HashMap, path::PathBuf};
 use tauri::{command, AppHandle, Manager, Runtime};
 
 #[command]
@@ -12,17 +17,108 @@ pub async fn save_window_state<R: Runtime>(
     Ok(())
 }
 
+/// Restores the window `label`, only showing it if `auto_show`, or [`AppHandleExt::auto_show`] if unset, is `true`.
 #[command]
 pub async fn restore_state<R: Runtime>(
     app: AppHandle<R>,
     label: String,
     flags: u32,
+    auto_show: Option<bool>,
 ) -> std::result::Result<(), String> {
     let flags = StateFlags::from_bits(flags)
         .ok_or_else(|| format!("Invalid state flags bits: {}", flags))?;
     app.get_window(&label)
         .ok_or_else(|| format!("Couldn't find window with label: {}", label))?
-        .restore_state(flags)
+        .restore_state_with(flags, auto_show.unwrap_or_else(|| app.auto_show()))
         .map_err(|e| e.to_string())?;
     Ok(())
 }
+
+/// The argument `clear_all_window_states` expects, so the destructive command can't be wired up by accident.
+const CLEAR_CONFIRMATION: &str = "clear-all-window-states";
+
+#[command]
+pub async fn clear_all_window_states<R: Runtime>(
+    app: AppHandle<R>,
+    confirmation: String,
+) -> std::result::Result<(), String> {
+    if confirmation != CLEAR_CONFIRMATION {
+        return Err(format!(
+            "Invalid confirmation, expected: {}",
+            CLEAR_CONFIRMATION
+        ));
+    }
+    app.clear_all_window_states().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Stores the current state of all open windows without saving it, returning the number of windows captured.
+#[command]
+pub async fn capture_current_layout<R: Runtime>(
+    app: AppHandle<R>,
+) -> std::result::Result<usize, String> {
+    app.capture_layout().map_err(|e| e.to_string())
+}
+
+/// Saves the stored state, for example a layout taken with `capture_current_layout`.
+#[command]
+pub async fn commit_layout<R: Runtime>(app: AppHandle<R>) -> std::result::Result<(), String> {
+    app.commit_layout().map_err(|e| e.to_string())
+}
+
+/// Returns the stored state of every window by label, for example for a settings screen
+/// listing the remembered windows. The plugin's own metadata isn't included.
+#[command]
+pub async fn get_all_window_states<R: Runtime>(app: AppHandle<R>) -> HashMap<String, WindowState> {
+    let mut states = HashMap::new();
+    app.for_each_window_state(|label, state| {
+        states.insert(label.to_string(), state.clone());
+    });
+    states
+}
+
+/// The active plugin configuration returned by `get_config`, for debugging.
+///
+/// Custom stores are only reported as such, since their configuration may contain credentials.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigReport {
+    state_flags: u32,
+    proportional_remap: bool,
+    default_tiling: Option<TilingMode>,
+    primary_relative_coords: bool,
+    fit_before_maximize: bool,
+    auto_show: bool,
+    custom_store: bool,
+    path: Option<PathBuf>,
+    /// The file name of [`ConfigReport::path`].
+    filename: Option<String>,
+    format: StateFormat,
+}
+
+#[command]
+pub async fn get_config<R: Runtime>(app: AppHandle<R>) -> ConfigReport {
+    let config = app.state::<PluginConfig>();
+    let store = app.state::<Store>();
+    let store = store.0.lock().unwrap();
+    let path = store
+        .as_ref()
+        .and_then(|store| store.path())
+        .map(|path| path.to_path_buf());
+    let filename = path
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().into_owned());
+    ConfigReport {
+        state_flags: config.state_flags.bits(),
+        proportional_remap: config.proportional_remap,
+        default_tiling: config.default_tiling,
+        primary_relative_coords: config.primary_relative_coords,
+        fit_before_maximize: config.fit_before_maximize,
+        auto_show: app.auto_show(),
+        custom_store: store.is_some() && path.is_none(),
+        path,
+        filename,
+        format: config.format,
+    }
+}
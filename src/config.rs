@@ -0,0 +1,114 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use serde::{de::Error as _, Deserialize, Deserializer};
+
+use std::path::PathBuf;
+
+use crate::{HiddenPolicy, StateFlags, StateFormat, TilingMode, UnknownPolicy};
+
+/// The plugin configuration in a serializable form, to build the plugin with [`crate::Builder::from_config`]
+/// from an app's own settings.
+///
+/// Every field is optional and mirrors the builder method of the same name:
+///
+/// ```
+/// # use tauri_plugin_window_state::WindowStateConfig;
+/// let config: WindowStateConfig = serde_json::from_str(
+///     r#"{
+///         "stateFlags": ["SIZE", "POSITION", "MAXIMIZED"],
+///         "denylist": ["splashscreen"],
+///         "skipInitialState": ["settings"],
+///         "filename": "window-state.bin",
+///         "reapplyAfterShow": false,
+///         "idleFlushMs": 1000,
+///         "moveDebounceMs": 500,
+///         "resizeDebounceMs": 100,
+///         "deferredRestoreMs": 0,
+///         "proportionalRemap": false,
+///         "saveDeltaThreshold": 20,
+///         "defaultTiling": "grid",
+///         "primaryRelativeCoords": false,
+///         "skipDuplicateLabels": false,
+///         "fitBeforeMaximize": false,
+///         "immediateFlags": ["MAXIMIZED", "FULLSCREEN"],
+///         "hiddenPolicy": "keepHidden",
+///         "unknownInLayout": "leaveAsIs",
+///         "restoreChangeThreshold": 50,
+///         "atomicRestore": false,
+///         "parallelRestore": false,
+///         "percentagePositions": false,
+///         "continueOnRestoreError": false,
+///         "detectWmOverride": false,
+///         "excludedMonitors": ["LG TV"],
+///         "maximizeDetectionThreshold": 0.95,
+///         "legacyPaths": ["/path/to/old/window-state.bin"],
+///         "liveBroadcast": false,
+///         "systemDefaultPath": "/etc/example/window-state.json",
+///         "format": "json",
+///         "annotations": false,
+///         "resetOnVersionChange": "1.2.0"
+///     }"#,
+/// )
+/// .unwrap();
+/// assert_eq!(config.idle_flush_ms, Some(1000));
+/// ```
+///
+/// `stateFlags` and `immediateFlags` are lists of [`StateFlags`] names, `defaultTiling` is
+/// either `"grid"` or `"columns"`, `hiddenPolicy` one of `"keepHidden"`, `"close"` or
+/// `"minimize"`, `unknownInLayout` one of `"leaveAsIs"`, `"hide"` or `"center"` and `format`
+/// either `"bincode"` or `"json"`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WindowStateConfig {
+    #[serde(deserialize_with = "deserialize_flags")]
+    pub state_flags: Option<StateFlags>,
+    pub denylist: Vec<String>,
+    pub skip_initial_state: Vec<String>,
+    pub filename: Option<String>,
+    pub reapply_after_show: bool,
+    pub idle_flush_ms: Option<u64>,
+    pub move_debounce_ms: Option<u64>,
+    pub resize_debounce_ms: Option<u64>,
+    pub deferred_restore_ms: Option<u64>,
+    pub proportional_remap: bool,
+    pub save_delta_threshold: Option<u32>,
+    pub default_tiling: Option<TilingMode>,
+    pub primary_relative_coords: bool,
+    pub skip_duplicate_labels: bool,
+    pub fit_before_maximize: bool,
+    #[serde(deserialize_with = "deserialize_flags")]
+    pub immediate_flags: Option<StateFlags>,
+    pub hidden_policy: HiddenPolicy,
+    pub unknown_in_layout: UnknownPolicy,
+    pub restore_change_threshold: Option<u32>,
+    pub atomic_restore: bool,
+    pub parallel_restore: bool,
+    pub percentage_positions: bool,
+    pub continue_on_restore_error: bool,
+    pub detect_wm_override: bool,
+    pub reset_on_version_change: Option<String>,
+    pub excluded_monitors: Vec<String>,
+    pub maximize_detection_threshold: Option<f64>,
+    pub legacy_paths: Vec<PathBuf>,
+    pub live_broadcast: bool,
+    pub system_default_path: Option<PathBuf>,
+    pub format: StateFormat,
+    pub annotations: bool,
+}
+
+fn deserialize_flags<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<StateFlags>, D::Error> {
+    let names = Option::<Vec<String>>::deserialize(deserializer)?;
+    names
+        .map(|names| {
+            names.iter().try_fold(StateFlags::empty(), |flags, name| {
+                StateFlags::from_name(&name.to_uppercase())
+                    .map(|flag| flags | flag)
+                    .ok_or_else(|| D::Error::custom(format!("unknown state flag `{}`", name)))
+            })
+        })
+        .transpose()
+}
@@ -0,0 +1,134 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Comparing layouts, for apps showing what applying a saved layout would change
+//! before applying it, for example with [`crate::AppHandleExt::restore_from_slot`].
+
+use serde::Serialize;
+use serde_json::Value;
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::WindowState;
+
+/// A difference between two layouts, see [`diff_layouts`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum LayoutDiff {
+    /// The window is only in the second layout.
+    Added { label: String },
+    /// The window is only in the first layout.
+    Removed { label: String },
+    /// The window is in both layouts with a different state.
+    Changed {
+        label: String,
+        changes: Vec<FieldChange>,
+    },
+}
+
+/// A field of [`WindowState`] that differs between two layouts.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    /// The name of the field, as it's serialized.
+    pub field: String,
+    /// The value in the first layout.
+    pub from: Value,
+    /// The value in the second layout.
+    pub to: Value,
+}
+
+/// Compares the window states in `a` with the ones in `b`, ordered by label.
+///
+/// Windows with equal states in both layouts aren't reported. Field values are compared
+/// in their JSON form, so nested fields like [`WindowState::monitor`] are reported as a whole.
+pub fn diff_layouts(
+    a: &HashMap<String, WindowState>,
+    b: &HashMap<String, WindowState>,
+) -> Vec<LayoutDiff> {
+    let labels: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    labels
+        .into_iter()
+        .filter_map(|label| match (a.get(label), b.get(label)) {
+            (Some(_), None) => Some(LayoutDiff::Removed {
+                label: label.clone(),
+            }),
+            (None, Some(_)) => Some(LayoutDiff::Added {
+                label: label.clone(),
+            }),
+            (Some(from), Some(to)) => {
+                let changes = diff_states(from, to);
+                (!changes.is_empty()).then(|| LayoutDiff::Changed {
+                    label: label.clone(),
+                    changes,
+                })
+            }
+            (None, None) => None,
+        })
+        .collect()
+}
+
+/// The fields that differ between `a` and `b`.
+fn diff_states(a: &WindowState, b: &WindowState) -> Vec<FieldChange> {
+    let fields = |state: &WindowState| match serde_json::to_value(state) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Default::default(),
+    };
+    let (a, mut b) = (fields(a), fields(b));
+    a.into_iter()
+        .filter_map(|(field, from)| {
+            let to = b.remove(&field).unwrap_or(Value::Null);
+            (from != to).then_some(FieldChange { field, from, to })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::layout;
+
+    #[test]
+    fn identical_layouts_have_no_diff() {
+        let a = layout(&[("main", 800.), ("settings", 400.)]);
+        assert!(diff_layouts(&a, &a.clone()).is_empty());
+        assert!(diff_layouts(&HashMap::new(), &HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn added_and_removed_labels() {
+        let a = layout(&[("main", 800.), ("old", 400.)]);
+        let b = layout(&[("main", 800.), ("new", 400.)]);
+        assert_eq!(
+            diff_layouts(&a, &b),
+            vec![
+                LayoutDiff::Added {
+                    label: "new".into()
+                },
+                LayoutDiff::Removed {
+                    label: "old".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_labels_list_their_fields() {
+        let a = layout(&[("main", 800.)]);
+        let mut b = layout(&[("main", 1024.)]);
+        b.get_mut("main").unwrap().maximized = true;
+
+        let diff = diff_layouts(&a, &b);
+        assert_eq!(diff.len(), 1);
+        let changes = match &diff[0] {
+            LayoutDiff::Changed { label, changes } if label == "main" => changes,
+            other => panic!("unexpected diff {:?}", other),
+        };
+        let mut fields: Vec<&str> = changes.iter().map(|c| c.field.as_str()).collect();
+        fields.sort_unstable();
+        assert_eq!(fields, vec!["maximized", "width"]);
+        let width = changes.iter().find(|c| c.field == "width").unwrap();
+        assert_eq!(width.from, serde_json::json!(800.0));
+        assert_eq!(width.to, serde_json::json!(1024.0));
+    }
+}
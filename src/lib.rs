@@ -3,24 +3,68 @@
 // SPDX-License-Identifier: MIT
 
 use bitflags::bitflags;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use tauri::{
     plugin::{Builder as PluginBuilder, TauriPlugin},
-    LogicalSize, Manager, Monitor, PhysicalPosition, PhysicalSize, RunEvent, Runtime, Window,
-    WindowEvent,
+    utils::config::WindowConfig,
+    LogicalSize, Manager, Monitor, PhysicalPosition, PhysicalSize, RunEvent, Runtime, Theme,
+    Window, WindowEvent,
 };
 
 use std::{
-    collections::{HashMap, HashSet},
-    fs::{create_dir_all, File},
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
     io::Write,
-    sync::{Arc, Mutex},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::ThreadId,
+    time::{Duration, Instant},
 };
 
 mod cmd;
+mod config;
+pub mod layout;
+mod store;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod validation;
+
+pub use config::WindowStateConfig;
+use store::tmp_path;
+#[cfg(feature = "native-store")]
+pub use store::NativeStore;
+pub use store::{FileStore, StateStore};
 
 pub const STATE_FILENAME: &str = ".window-state";
 
+/// Whether geometry set on a hidden window has to be applied again once it's shown,
+/// since some Linux window managers ignore or override it.
+const REAPPLY_GEOMETRY_AFTER_SHOW: bool = cfg!(target_os = "linux");
+
+/// How long to wait before re-applying geometry when [`Builder::with_reapply_after_show`] is set.
+const REAPPLY_AFTER_SHOW_DELAY: Duration = Duration::from_millis(300);
+
+/// How often a changing window is broadcast with [`Builder::with_live_broadcast`]
+/// when no move or resize debounce applies.
+const LIVE_BROADCAST_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The logical inner size a maximized window without a usable normal size gets if it has no size yet either.
+const FALLBACK_NORMAL_SIZE: LogicalSize<f64> = LogicalSize {
+    width: 800.,
+    height: 600.,
+};
+
+/// How long after a restore the geometry is read again when [`Builder::with_detect_wm_override`] is set.
+const WM_OVERRIDE_DELAY: Duration = Duration::from_millis(500);
+
+/// How many physical pixels an edge or dimension may differ from the restored geometry
+/// before the window manager is assumed to have overridden it.
+const WM_OVERRIDE_THRESHOLD: u32 = 32;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error(transparent)]
@@ -31,10 +75,100 @@ pub enum Error {
     TauriApi(#[from] tauri::api::Error),
     #[error(transparent)]
     Bincode(#[from] Box<bincode::ErrorKind>),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("no window state stored for label `{0}`")]
+    StateNotFound(String),
+    #[error("a window state for label `{0}` already exists")]
+    StateAlreadyExists(String),
+    #[error("the window state is {0} bytes, more than the allowed 16 MiB")]
+    StateTooLarge(usize),
+    #[error("layout slot {0} doesn't exist, slots are numbered 0 to 9")]
+    InvalidSlot(u8),
+    #[error("no layout saved to slot {0}")]
+    SlotEmpty(u8),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Invalid plugin configuration, returned by [`Builder::try_build`].
+#[derive(Debug, thiserror::Error)]
+pub enum BuilderError {
+    #[error("the state filename must not be empty")]
+    EmptyFilename,
+    #[error("the state filename `{0}` must be a file name, not a path")]
+    InvalidFilename(String),
+    #[error("a state filename can't be combined with a custom store")]
+    FilenameWithStore,
+    #[error("the idle flush duration must be greater than zero")]
+    ZeroIdleFlush,
+    #[error(
+        "the restore interceptor was set for a different runtime than the plugin is built for"
+    )]
+    InterceptorRuntimeMismatch,
+    #[error("the maximize detection threshold must be greater than 0 and at most 1, got {0}")]
+    InvalidMaximizeThreshold(f64),
+}
+
+/// Non-fatal issues the plugin encountered, routed to the handler set with [`Builder::with_warning_handler`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Warning {
+    /// The stored size of a window didn't fit on any monitor and was clamped to the size of `monitor`.
+    OversizeRestore {
+        label: String,
+        monitor: Option<String>,
+        requested: LogicalSize<f64>,
+        clamped: LogicalSize<f64>,
+    },
+    /// A window was created while another tracked window with the same label was still open,
+    /// so both would write to the same stored state.
+    DuplicateLabel { label: String },
+    /// A loaded window state was discarded because it was malformed
+    /// or exceeded the number of states the plugin loads.
+    RejectedState { label: String, reason: String },
+    /// Restoring a window failed to apply a property, `setter` naming the window operation that failed.
+    RestoreSetterFailed {
+        label: String,
+        setter: &'static str,
+        error: String,
+    },
+    /// A window was stored maximized without a usable normal size, so it was given
+    /// `substituted` and centered before maximizing, to have somewhere to un-maximize to.
+    InvalidNormalSize {
+        label: String,
+        substituted: LogicalSize<f64>,
+    },
+    /// The window manager moved or resized a window right after it was restored,
+    /// see [`Builder::with_detect_wm_override`].
+    WindowManagerOverride {
+        label: String,
+        requested: validation::Rect,
+        actual: validation::Rect,
+    },
+}
+
+type WarningHandlerFn = dyn Fn(Warning) + Send + Sync + 'static;
+
+type EventObserverFn = dyn Fn(&str, &WindowEvent) + Send + Sync + 'static;
+
+type CursorPositionFn = dyn Fn() -> Option<PhysicalPosition<i32>> + Send + Sync + 'static;
+
+/// Locates the cursor for [`Builder::with_restore_to_cursor_monitor`].
+struct CursorPosition(Option<Box<CursorPositionFn>>);
+
+struct WarningHandler(Option<Box<WarningHandlerFn>>);
+
+/// Routes `warning` to the user-provided handler, or logs it if there is none.
+fn emit_warning<R: Runtime, M: Manager<R>>(manager: &M, warning: Warning) {
+    let handler = manager.try_state::<WarningHandler>();
+    if let Some(handler) = handler.as_ref().and_then(|h| h.0.as_ref()) {
+        handler(warning);
+    } else {
+        log::warn!("{:?}", warning);
+    }
+}
+
 bitflags! {
     #[derive(Clone, Copy, Debug)]
     pub struct StateFlags: u32 {
@@ -44,6 +178,9 @@ bitflags! {
         const VISIBLE     = 1 << 3;
         const DECORATIONS = 1 << 4;
         const FULLSCREEN  = 1 << 5;
+        const SNAP        = 1 << 6;
+        const THEME       = 1 << 7;
+        const ZOOM        = 1 << 8;
     }
 }
 
@@ -53,21 +190,89 @@ impl Default for StateFlags {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
-struct WindowState {
-    width: f64,
-    height: f64,
-    x: i32,
-    y: i32,
+/// The stored state of a window.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct WindowState {
+    /// Logical inner width.
+    pub width: f64,
+    /// Logical inner height.
+    pub height: f64,
+    /// Physical outer x position.
+    pub x: i32,
+    /// Physical outer y position.
+    pub y: i32,
     // prev_x and prev_y are used to store position
     // before maximization happened, because maximization
     // will set x and y to the top-left corner of the monitor
-    prev_x: i32,
-    prev_y: i32,
-    maximized: bool,
-    visible: bool,
-    decorated: bool,
-    fullscreen: bool,
+    pub prev_x: i32,
+    pub prev_y: i32,
+    pub maximized: bool,
+    pub visible: bool,
+    pub decorated: bool,
+    #[serde(deserialize_with = "deserialize_fullscreen")]
+    pub fullscreen: FullscreenMode,
+    /// The monitor the window was on when its state was saved.
+    #[serde(default)]
+    pub monitor: Option<MonitorInfo>,
+    /// The half or quarter of its monitor the window covered when its state was saved.
+    #[serde(default)]
+    pub snap: Option<SnapPosition>,
+    /// Arbitrary data the app stored for the window with [`WindowExt::set_user_data`].
+    #[serde(default, with = "user_data")]
+    pub user_data: Option<serde_json::Value>,
+    /// The names of the monitors the window overlapped when its state was saved,
+    /// empty unless it spanned more than one monitor.
+    ///
+    /// A spanning window is only restored at its full size if all of these monitors are connected,
+    /// otherwise its size is clamped to a single monitor like any oversized window.
+    #[serde(default)]
+    pub spanned_monitors: Vec<String>,
+    /// The theme of the window when its state was saved, recorded with [`StateFlags::THEME`].
+    ///
+    /// Tauri can't change the theme of an existing window, so restoring doesn't apply it.
+    /// Apps with per-window themes can read it to create the window with that theme.
+    #[serde(default)]
+    pub theme: Option<Theme>,
+    /// The position and size as fractions of [`WindowState::monitor`],
+    /// recorded with [`Builder::with_percentage_positions`].
+    #[serde(default)]
+    pub fractions: Option<GeometryFractions>,
+    /// The webview zoom factor the app stored with [`WindowExt::set_zoom_persisted`].
+    #[serde(default)]
+    pub zoom: Option<f64>,
+    /// The name of the monitor the window was maximized on, if it was maximized when its state was saved.
+    #[serde(default)]
+    pub maximized_monitor: Option<String>,
+    /// Whether the window was minimized when its state was saved, recorded with [`StateFlags::VISIBLE`].
+    ///
+    /// Restoring doesn't minimize windows. While a window is minimized, its size and position
+    /// aren't saved, since some platforms report a zero size and an offscreen position for it.
+    #[serde(default)]
+    pub minimized: bool,
+    /// The window this window is placed relative to, set with [`WindowExt::set_relative_to`].
+    #[serde(default)]
+    pub relative_to: Option<RelativePlacement>,
+}
+
+/// A position relative to another window, see [`WindowExt::set_relative_to`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct RelativePlacement {
+    /// The label of the parent window.
+    pub parent: String,
+    /// The physical offset of this window's outer position from the parent's outer position.
+    pub offset: PhysicalPosition<i32>,
+}
+
+/// A position and size relative to a monitor, from `0.0` at its top-left to `1.0` at its bottom-right edge.
+///
+/// The fractions are taken of the physical monitor size, so the logical size of a window
+/// changes along with the scale factor of the monitor it is restored on.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct GeometryFractions {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
 }
 
 impl Default for WindowState {
@@ -83,340 +288,5730 @@ impl Default for WindowState {
             visible: true,
             decorated: true,
             fullscreen: Default::default(),
+            monitor: Default::default(),
+            snap: Default::default(),
+            user_data: Default::default(),
+            spanned_monitors: Default::default(),
+            theme: Default::default(),
+            fractions: Default::default(),
+            zoom: Default::default(),
+            maximized_monitor: Default::default(),
+            minimized: Default::default(),
+            relative_to: Default::default(),
         }
     }
 }
 
-struct WindowStateCache(Arc<Mutex<HashMap<String, WindowState>>>);
-pub trait AppHandleExt {
-    /// Saves all open windows state to disk
-    fn save_window_state(&self, flags: StateFlags) -> Result<()>;
-}
-
-impl<R: Runtime> AppHandleExt for tauri::AppHandle<R> {
-    fn save_window_state(&self, flags: StateFlags) -> Result<()> {
-        if let Some(app_dir) = self.path_resolver().app_config_dir() {
-            let state_path = app_dir.join(STATE_FILENAME);
-            let cache = self.state::<WindowStateCache>();
-            let mut state = cache.0.lock().unwrap();
-            for (label, s) in state.iter_mut() {
-                if let Some(window) = self.get_window(label) {
-                    window.update_state(s, flags)?;
-                }
-            }
-
-            create_dir_all(&app_dir)
-                .map_err(Error::Io)
-                .and_then(|_| File::create(state_path).map_err(Into::into))
-                .and_then(|mut f| {
-                    f.write_all(&bincode::serialize(&*state).map_err(Error::Bincode)?)
-                        .map_err(Into::into)
-                })
-        } else {
-            Ok(())
+impl WindowState {
+    /// Moves the position and the saved monitor by `dx` and `dy` physical pixels.
+    fn translate(&mut self, dx: i32, dy: i32) {
+        self.x += dx;
+        self.y += dy;
+        self.prev_x += dx;
+        self.prev_y += dy;
+        if let Some(monitor) = &mut self.monitor {
+            monitor.x += dx;
+            monitor.y += dy;
         }
     }
-}
 
-pub trait WindowExt {
-    /// Restores this window state from disk
-    fn restore_state(&self, flags: StateFlags) -> tauri::Result<()>;
-}
+    /// The position and size as fractions of the monitor the state was saved on.
+    fn monitor_fractions(&self) -> Option<GeometryFractions> {
+        let saved = self
+            .monitor
+            .as_ref()
+            .filter(|m| m.width > 0 && m.height > 0)?;
+        Some(GeometryFractions {
+            x: (self.x - saved.x) as f64 / saved.width as f64,
+            y: (self.y - saved.y) as f64 / saved.height as f64,
+            width: self.width * saved.scale_factor / saved.width as f64,
+            height: self.height * saved.scale_factor / saved.height as f64,
+        })
+    }
 
-impl<R: Runtime> WindowExt for Window<R> {
-    fn restore_state(&self, flags: StateFlags) -> tauri::Result<()> {
-        let cache = self.state::<WindowStateCache>();
-        let mut c = cache.0.lock().unwrap();
+    /// Applies `fractions` to `target`, replacing the position and size.
+    fn apply_fractions(&mut self, fractions: GeometryFractions, target: &MonitorInfo) {
+        self.x = target.x + (fractions.x * target.width as f64).round() as i32;
+        self.y = target.y + (fractions.y * target.height as f64).round() as i32;
+        self.width = fractions.width * target.width as f64 / target.scale_factor;
+        self.height = fractions.height * target.height as f64 / target.scale_factor;
+        self.monitor = Some(target.clone());
+    }
 
-        let mut should_show = true;
+    /// Whether this is the default state with at most data that isn't geometry, like [`WindowState::user_data`].
+    fn geometry_unset(&self) -> bool {
+        WindowState {
+            user_data: None,
+            zoom: None,
+            relative_to: None,
+            ..self.clone()
+        } == WindowState::default()
+    }
 
-        if let Some(state) = c.get(self.label()) {
-            // avoid restoring the default zeroed state
-            if *state == WindowState::default() {
-                return Ok(());
-            }
+    /// Maps the position and size onto `target`, keeping them at the same
+    /// fractions of the monitor the state was saved on.
+    ///
+    /// `x' = target.x + (x - saved.x) / saved.width * target.width` and likewise for `y`,
+    /// while sizes are compared in physical pixels to account for differing scale factors.
+    fn remap_to(&mut self, target: &MonitorInfo) {
+        if self.monitor.as_ref() == Some(target) {
+            return;
+        }
 
-            if flags.contains(StateFlags::DECORATIONS) {
-                self.set_decorations(state.decorated)?;
-            }
+        if let Some(fractions) = self.monitor_fractions() {
+            self.apply_fractions(fractions, target);
+        }
+    }
+}
 
-            if flags.contains(StateFlags::SIZE) {
-                self.set_size(LogicalSize {
-                    width: state.width,
-                    height: state.height,
-                })?;
-            }
+/// How far in physical pixels the edges of a window may be off the half or quarter
+/// of its monitor to still be detected as snapped there.
+const SNAP_TOLERANCE: i64 = 16;
 
-            if flags.contains(StateFlags::POSITION) {
-                let position = (state.x, state.y).into();
-                let size = (state.width, state.height).into();
-                // restore position to saved value if saved monitor exists
-                // otherwise, let the OS decide where to place the window
-                for m in self.available_monitors()? {
-                    if m.intersects(position, size) {
-                        self.set_position(PhysicalPosition {
-                            x: if state.maximized {
-                                state.prev_x
-                            } else {
-                                state.x
-                            },
-                            y: if state.maximized {
-                                state.prev_y
-                            } else {
-                                state.y
-                            },
-                        })?;
-                    }
-                }
-            }
+/// A half or quarter of a monitor a window is snapped to, restored with [`StateFlags::SNAP`].
+///
+/// Tauri doesn't expose the monitor work area or the native snap state, so snapping is
+/// detected by comparing the outer bounds of a window to the halves and quarters of its
+/// monitor with a tolerance of a few pixels, and restored by applying those bounds again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SnapPosition {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
 
-            if flags.contains(StateFlags::MAXIMIZED) && state.maximized {
-                self.maximize()?;
-            }
+impl SnapPosition {
+    const ALL: [Self; 8] = [
+        Self::Left,
+        Self::Right,
+        Self::Top,
+        Self::Bottom,
+        Self::TopLeft,
+        Self::TopRight,
+        Self::BottomLeft,
+        Self::BottomRight,
+    ];
 
-            if flags.contains(StateFlags::FULLSCREEN) {
-                self.set_fullscreen(state.fullscreen)?;
+    /// The part of `area` a window snapped this way covers.
+    fn rect(&self, area: validation::Rect) -> validation::Rect {
+        let (half_width, half_height) = (area.width / 2, area.height / 2);
+        let (x, width) = match self {
+            Self::Left | Self::TopLeft | Self::BottomLeft => (area.x, half_width),
+            Self::Right | Self::TopRight | Self::BottomRight => {
+                (area.x + half_width as i32, area.width - half_width)
             }
+            Self::Top | Self::Bottom => (area.x, area.width),
+        };
+        let (y, height) = match self {
+            Self::Top | Self::TopLeft | Self::TopRight => (area.y, half_height),
+            Self::Bottom | Self::BottomLeft | Self::BottomRight => {
+                (area.y + half_height as i32, area.height - half_height)
+            }
+            Self::Left | Self::Right => (area.y, area.height),
+        };
+        validation::Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
 
-            should_show = state.visible;
-        } else {
-            let mut metadata = WindowState::default();
+    /// The snap position whose part of `area` matches `window` within [`SNAP_TOLERANCE`].
+    fn detect(window: validation::Rect, area: validation::Rect) -> Option<Self> {
+        Self::ALL.into_iter().find(|snap| {
+            let target = snap.rect(area);
+            [
+                (window.x as i64, target.x as i64),
+                (window.y as i64, target.y as i64),
+                (window.width as i64, target.width as i64),
+                (window.height as i64, target.height as i64),
+            ]
+            .into_iter()
+            .all(|(a, b)| (a - b).abs() <= SNAP_TOLERANCE)
+        })
+    }
+}
 
-            if flags.contains(StateFlags::SIZE) {
-                let scale_factor = self
-                    .current_monitor()?
-                    .map(|m| m.scale_factor())
-                    .unwrap_or(1.);
-                let size = self.inner_size()?.to_logical(scale_factor);
-                metadata.width = size.width;
-                metadata.height = size.height;
-            }
+/// How a window covers its monitor when in fullscreen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FullscreenMode {
+    #[default]
+    None,
+    /// A borderless window covering the monitor.
+    Borderless,
+    /// Exclusive fullscreen with a video mode change.
+    ///
+    /// Tauri doesn't expose exclusive fullscreen yet, so this is restored with
+    /// [`Window::set_fullscreen`] like [`FullscreenMode::Borderless`]. It's only
+    /// recorded when set by the app, as saving can't tell the modes apart.
+    Exclusive,
+}
 
-            if flags.contains(StateFlags::POSITION) {
-                let pos = self.outer_position()?;
-                metadata.x = pos.x;
-                metadata.y = pos.y;
-            }
+impl FullscreenMode {
+    pub fn is_fullscreen(&self) -> bool {
+        *self != Self::None
+    }
+}
 
-            if flags.contains(StateFlags::MAXIMIZED) {
-                metadata.maximized = self.is_maximized()?;
-            }
+impl From<bool> for FullscreenMode {
+    fn from(fullscreen: bool) -> Self {
+        if fullscreen {
+            Self::Borderless
+        } else {
+            Self::None
+        }
+    }
+}
 
-            if flags.contains(StateFlags::VISIBLE) {
-                metadata.visible = self.is_visible()?;
-            }
+/// Deserializes a [`FullscreenMode`], also accepting the boolean that human-readable
+/// state files stored before the modes existed.
+fn deserialize_fullscreen<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<FullscreenMode, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Legacy(bool),
+        Mode(FullscreenMode),
+    }
 
-            if flags.contains(StateFlags::DECORATIONS) {
-                metadata.decorated = self.is_decorated()?;
-            }
+    if deserializer.is_human_readable() {
+        Repr::deserialize(deserializer).map(|repr| match repr {
+            Repr::Legacy(fullscreen) => fullscreen.into(),
+            Repr::Mode(mode) => mode,
+        })
+    } else {
+        FullscreenMode::deserialize(deserializer)
+    }
+}
 
-            if flags.contains(StateFlags::FULLSCREEN) {
-                metadata.fullscreen = self.is_fullscreen()?;
-            }
+/// (De)serializes [`WindowState::user_data`] as JSON nested in the human-readable format,
+/// and as a JSON string otherwise, since formats like bincode can't represent arbitrary values.
+mod user_data {
+    use serde::{
+        de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer,
+    };
+    use serde_json::Value;
 
-            c.insert(self.label().into(), metadata);
+    pub fn serialize<S: Serializer>(
+        value: &Option<Value>,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            value.serialize(serializer)
+        } else {
+            value
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()
+                .map_err(S::Error::custom)?
+                .serialize(serializer)
         }
+    }
 
-        if flags.contains(StateFlags::VISIBLE) && should_show {
-            self.show()?;
-            self.set_focus()?;
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Option<Value>, D::Error> {
+        if deserializer.is_human_readable() {
+            Option::<Value>::deserialize(deserializer)
+        } else {
+            Option::<String>::deserialize(deserializer)?
+                .map(|json| serde_json::from_str(&json))
+                .transpose()
+                .map_err(D::Error::custom)
         }
-
-        Ok(())
     }
 }
 
-trait WindowExtInternal {
-    fn update_state(&self, state: &mut WindowState, flags: StateFlags) -> tauri::Result<()>;
+/// The geometry of a monitor, in physical pixels.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct MonitorInfo {
+    pub name: Option<String>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f64,
 }
 
-impl<R: Runtime> WindowExtInternal for Window<R> {
-    fn update_state(&self, state: &mut WindowState, flags: StateFlags) -> tauri::Result<()> {
-        let is_maximized = match flags.intersects(StateFlags::MAXIMIZED | StateFlags::SIZE) {
-            true => self.is_maximized()?,
-            false => false,
-        };
-
-        if flags.contains(StateFlags::MAXIMIZED) {
-            state.maximized = is_maximized;
+impl From<&Monitor> for MonitorInfo {
+    fn from(monitor: &Monitor) -> Self {
+        Self {
+            name: monitor.name().cloned(),
+            x: monitor.position().x,
+            y: monitor.position().y,
+            width: monitor.size().width,
+            height: monitor.size().height,
+            scale_factor: monitor.scale_factor(),
         }
+    }
+}
 
-        if flags.contains(StateFlags::FULLSCREEN) {
-            state.fullscreen = self.is_fullscreen()?;
-        }
+/// The layout of [`WindowState`] written by versions 0.1 and earlier.
+#[derive(Deserialize)]
+struct LegacyWindowState {
+    width: f64,
+    height: f64,
+    x: i32,
+    y: i32,
+    prev_x: i32,
+    prev_y: i32,
+    maximized: bool,
+    visible: bool,
+    decorated: bool,
+    fullscreen: bool,
+}
 
-        if flags.contains(StateFlags::DECORATIONS) {
-            state.decorated = self.is_decorated()?;
+impl From<LegacyWindowState> for WindowState {
+    fn from(legacy: LegacyWindowState) -> Self {
+        Self {
+            width: legacy.width,
+            height: legacy.height,
+            x: legacy.x,
+            y: legacy.y,
+            prev_x: legacy.prev_x,
+            prev_y: legacy.prev_y,
+            maximized: legacy.maximized,
+            visible: legacy.visible,
+            decorated: legacy.decorated,
+            fullscreen: legacy.fullscreen.into(),
+            ..Default::default()
         }
+    }
+}
 
-        if flags.contains(StateFlags::VISIBLE) {
-            state.visible = self.is_visible()?;
-        }
+struct WindowStateCache(Arc<Mutex<HashMap<String, WindowState>>>);
 
-        if flags.contains(StateFlags::SIZE) {
-            let scale_factor = self
-                .current_monitor()?
-                .map(|m| m.scale_factor())
-                .unwrap_or(1.);
-            let size = self.inner_size()?.to_logical(scale_factor);
+/// Labels of windows currently inside a [`WindowExt::begin_programmatic`] bracket,
+/// mapped to the nesting depth of the bracket.
+#[derive(Default)]
+struct ProgrammaticWindows(Arc<Mutex<HashMap<String, usize>>>);
+
+/// The geometries stashed with [`WindowExt::stash_state`], by token, with the label of their window.
+#[derive(Default)]
+struct Stashes {
+    next: AtomicU64,
+    states: Mutex<HashMap<u64, (String, WindowState)>>,
+}
+
+/// The thread the event loop runs on, the one the plugin was set up on.
+struct EventLoopThread(ThreadId);
 
-            // It doesn't make sense to save a window with 0 height or width
-            if size.width > 0. && size.height > 0. && !is_maximized {
-                state.width = size.width;
-                state.height = size.height;
+/// Labels of open tracked windows, mapped to the number of open windows with that label.
+#[derive(Clone, Default)]
+struct LiveWindows(Arc<Mutex<HashMap<String, usize>>>);
+
+impl LiveWindows {
+    /// Counts a window labeled `label` as open, returning whether another open window has that label.
+    fn open(&self, label: &str) -> bool {
+        let mut live = self.0.lock().unwrap();
+        let count = live.entry(label.into()).or_default();
+        *count += 1;
+        *count > 1
+    }
+
+    /// Counts a window labeled `label` as closed.
+    fn close(&self, label: &str) {
+        let mut live = self.0.lock().unwrap();
+        if let Some(count) = live.get_mut(label) {
+            *count -= 1;
+            if *count == 0 {
+                live.remove(label);
             }
         }
+    }
+}
 
-        if flags.contains(StateFlags::POSITION) && !is_maximized {
-            let position = self.outer_position()?;
-            state.x = position.x;
-            state.y = position.y;
-        }
+/// Whether saving is paused for a window with [`WindowExt::set_save_enabled`], by label.
+///
+/// The flags are shared with the event handlers, so they check them without locking the map.
+#[derive(Default)]
+struct SavePaused(Mutex<HashMap<String, Arc<AtomicBool>>>);
 
-        Ok(())
+impl SavePaused {
+    fn flag(&self, label: &str) -> Arc<AtomicBool> {
+        self.0
+            .lock()
+            .unwrap()
+            .entry(label.into())
+            .or_default()
+            .clone()
+    }
+
+    fn is_paused(&self, label: &str) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .get(label)
+            .map_or(false, |paused| paused.load(Ordering::Relaxed))
     }
 }
 
+/// Per-window overrides set with [`WindowExt::set_state_persistence`].
 #[derive(Default)]
-pub struct Builder {
-    denylist: HashSet<String>,
-    skip_initial_state: HashSet<String>,
-    state_flags: StateFlags,
+struct PersistencePreferences(Arc<Mutex<HashMap<String, bool>>>);
+
+/// The kind of change to a window, so moves and resizes are debounced independently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ChangeKind {
+    Moved,
+    Resized,
 }
 
-impl Builder {
-    pub fn new() -> Self {
-        Self::default()
-    }
+enum SaveMessage {
+    /// The window with the label changed and should be flushed once it was idle for the duration.
+    Changed(String, ChangeKind, Duration),
+    /// The window with the label changed and should be flushed right away.
+    Dirty(String, ChangeKind),
+    /// The window with the label changed and should be broadcast at most once per the duration.
+    Broadcast(String, Duration),
+    Stop,
+}
 
-    /// Sets the state flags to control what state gets restored and saved.
-    pub fn with_state_flags(mut self, flags: StateFlags) -> Self {
-        self.state_flags = flags;
-        self
-    }
+/// Notifies the save worker of changed windows, the single thread writing the state
+/// while the app runs, so windows changing at the same time cause a single write.
+///
+/// Windows with an idle duration, set with [`Builder::with_idle_flush`] or
+/// [`WindowExt::set_autosave_interval`], are flushed once they were left untouched for it.
+struct SaveWorker {
+    tx: Mutex<Option<Sender<SaveMessage>>>,
+    default: Option<Duration>,
+    overrides: Mutex<HashMap<String, Duration>>,
+}
 
-    /// Sets a list of windows that shouldn't be tracked and managed by this plugin
-    /// for example splash screen windows.
-    pub fn with_denylist(mut self, denylist: &[&str]) -> Self {
-        self.denylist = denylist.iter().map(|l| l.to_string()).collect();
-        self
+impl SaveWorker {
+    fn idle_for(&self, label: &str) -> Option<Duration> {
+        self.overrides
+            .lock()
+            .unwrap()
+            .get(label)
+            .copied()
+            .or(self.default)
     }
 
-    /// Adds the given window label to a list of windows to skip initial state restore.
-    pub fn skip_initial_state(mut self, label: &str) -> Self {
-        self.skip_initial_state.insert(label.into());
-        self
+    fn is_enabled(&self, label: &str) -> bool {
+        self.idle_for(label).is_some()
     }
 
-    pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
-        let flags = self.state_flags;
-        PluginBuilder::new("window-state")
-            .invoke_handler(tauri::generate_handler![
-                cmd::save_window_state,
-                cmd::restore_state
-            ])
-            .setup(|app| {
-                let cache: Arc<Mutex<HashMap<String, WindowState>>> = if let Some(app_dir) =
-                    app.path_resolver().app_config_dir()
-                {
-                    let state_path = app_dir.join(STATE_FILENAME);
-                    if state_path.exists() {
-                        Arc::new(Mutex::new(
-                            tauri::api::file::read_binary(state_path)
-                                .map_err(Error::TauriApi)
-                                .and_then(|state| bincode::deserialize(&state).map_err(Into::into))
-                                .unwrap_or_default(),
-                        ))
-                    } else {
-                        Default::default()
-                    }
-                } else {
-                    Default::default()
-                };
-                app.manage(WindowStateCache(cache));
-                Ok(())
-            })
-            .on_webview_ready(move |window| {
-                if self.denylist.contains(window.label()) {
-                    return;
-                }
-
-                if !self.skip_initial_state.contains(window.label()) {
-                    let _ = window.restore_state(self.state_flags);
-                }
-
-                let cache = window.state::<WindowStateCache>();
-                let cache = cache.0.clone();
-                let label = window.label().to_string();
-                let window_clone = window.clone();
-                let flags = self.state_flags;
+    fn notify(&self, label: &str, kind: ChangeKind) {
+        if let Some(idle) = self.idle_for(label) {
+            if let Some(tx) = &*self.tx.lock().unwrap() {
+                let _ = tx.send(SaveMessage::Changed(label.into(), kind, idle));
+            }
+        }
+    }
 
-                // insert a default state if this window should be tracked and
-                // the disk cache doesn't have a state for it
-                {
-                    cache
-                        .lock()
-                        .unwrap()
-                        .entry(label.clone())
-                        .or_insert_with(WindowState::default);
-                }
+    /// Asks the worker to save once the window with the label didn't change in the way of `kind`
+    /// for `delay`, regardless of pending saves for other kinds of changes.
+    fn debounce(&self, label: &str, kind: ChangeKind, delay: Duration) {
+        if let Some(tx) = &*self.tx.lock().unwrap() {
+            let _ = tx.send(SaveMessage::Changed(label.into(), kind, delay));
+        }
+    }
 
-                window.on_window_event(move |e| match e {
-                    WindowEvent::CloseRequested { .. } => {
-                        let mut c = cache.lock().unwrap();
-                        if let Some(state) = c.get_mut(&label) {
-                            let _ = window_clone.update_state(state, flags);
-                        }
-                    }
+    /// Asks the worker to save the changed window soon, along with other windows changing meanwhile.
+    fn mark_dirty(&self, label: &str, kind: ChangeKind) {
+        if let Some(tx) = &*self.tx.lock().unwrap() {
+            let _ = tx.send(SaveMessage::Dirty(label.into(), kind));
+        }
+    }
 
-                    WindowEvent::Moved(position) if flags.contains(StateFlags::POSITION) => {
-                        let mut c = cache.lock().unwrap();
-                        if let Some(state) = c.get_mut(&label) {
-                            state.prev_x = state.x;
-                            state.prev_y = state.y;
+    /// Asks the worker to broadcast the window with the label within `interval`,
+    /// see [`Builder::with_live_broadcast`].
+    fn broadcast(&self, label: &str, interval: Duration) {
+        if let Some(tx) = &*self.tx.lock().unwrap() {
+            let _ = tx.send(SaveMessage::Broadcast(label.into(), interval));
+        }
+    }
 
-                            state.x = position.x;
-                            state.y = position.y;
-                        }
-                    }
-                    _ => {}
-                });
-            })
-            .on_event(move |app, event| {
-                if let RunEvent::Exit = event {
-                    let _ = app.save_window_state(flags);
-                }
-            })
-            .build()
+    /// Cancels any pending flush and shuts the worker down.
+    ///
+    /// The final flush on exit is up to the caller: window operations from the worker
+    /// would wait on the event loop that is exiting.
+    fn stop(&self) {
+        if let Some(tx) = self.tx.lock().unwrap().take() {
+            let _ = tx.send(SaveMessage::Stop);
+        }
     }
 }
 
-trait MonitorExt {
-    fn intersects(&self, position: PhysicalPosition<i32>, size: LogicalSize<u32>) -> bool;
+/// Saves the state once a changed window is due, coalescing all changes
+/// received until then into one write.
+///
+/// Like [`update_from_windows`], the worker never reads a window while holding a lock
+/// of the plugin: saves go through it and broadcasts read the window after
+/// cloning the cached state.
+fn save_worker<R: Runtime>(app: tauri::AppHandle<R>, rx: Receiver<SaveMessage>) {
+    run_save_worker(
+        rx,
+        || {
+            let _ = save_tracked_state(&app);
+        },
+        |label| broadcast_state(&app, label),
+    );
 }
 
-impl MonitorExt for Monitor {
-    fn intersects(&self, position: PhysicalPosition<i32>, size: LogicalSize<u32>) -> bool {
-        let size = size.to_physical::<u32>(self.scale_factor());
+/// The loop of [`save_worker`], calling `save` once changes are due to be flushed
+/// and `broadcast` with the label of windows due to be broadcast.
+fn run_save_worker(
+    rx: Receiver<SaveMessage>,
+    mut save: impl FnMut(),
+    mut broadcast: impl FnMut(&str),
+) {
+    // when each kind of change of each window is due to be flushed, queued messages
+    // are received before a due deadline times out, so they are part of the same write
+    let mut deadlines: HashMap<(String, ChangeKind), Instant> = HashMap::new();
+    let mut broadcasts: HashMap<String, Instant> = HashMap::new();
+    loop {
+        let message = match deadlines.values().chain(broadcasts.values()).min().copied() {
+            Some(deadline) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match message {
+            Ok(SaveMessage::Changed(label, kind, idle)) => {
+                deadlines.insert((label, kind), Instant::now() + idle);
+            }
+            Ok(SaveMessage::Dirty(label, kind)) => {
+                deadlines.insert((label, kind), Instant::now());
+            }
+            // keep the first deadline, so a window changing continuously is broadcast periodically
+            Ok(SaveMessage::Broadcast(label, interval)) => {
+                broadcasts
+                    .entry(label)
+                    .or_insert_with(|| Instant::now() + interval);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let now = Instant::now();
+                let due: Vec<String> = broadcasts
+                    .iter()
+                    .filter(|(_, deadline)| **deadline <= now)
+                    .map(|(label, _)| label.clone())
+                    .collect();
+                for label in due {
+                    broadcasts.remove(&label);
+                    broadcast(&label);
+                }
+                // the save covers every window, but changes that aren't due keep their deadline
+                if deadlines.values().any(|deadline| *deadline <= now) {
+                    save();
+                    deadlines.retain(|_, deadline| *deadline > now);
+                }
+            }
+            Ok(SaveMessage::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Emits [`STATE_CHANGED_EVENT`] with the current state of the window `label`, without storing it.
+fn broadcast_state<R: Runtime>(app: &tauri::AppHandle<R>, label: &str) {
+    let window = match app.get_window(label) {
+        Some(window) => window,
+        None => return,
+    };
+    let mut state = app
+        .state::<WindowStateCache>()
+        .0
+        .lock()
+        .unwrap()
+        .get(label)
+        .cloned()
+        .unwrap_or_default();
+    if window
+        .update_state(&mut state, window.tracked_flags())
+        .is_ok()
+    {
+        let _ = app.emit_all(
+            STATE_CHANGED_EVENT,
+            WindowStateChange {
+                label: label.into(),
+                state,
+            },
+        );
+    }
+}
 
-        let PhysicalPosition { x, y } = *self.position();
-        let PhysicalSize { width, height } = *self.size();
+/// The outer geometry of a window in physical pixels.
+#[derive(Clone, Copy)]
+struct Geometry {
+    position: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+}
 
-        let left = x;
-        let right = x + width as i32;
-        let top = y;
-        let bottom = y + height as i32;
+impl Geometry {
+    fn of<R: Runtime>(window: &Window<R>) -> tauri::Result<Self> {
+        Ok(Self {
+            position: window.outer_position()?,
+            size: window.inner_size()?,
+        })
+    }
 
+    /// The largest change along any edge or dimension between the two geometries.
+    fn delta(&self, other: &Self) -> u32 {
         [
-            (position.x, position.y),
-            (position.x + size.width as i32, position.y),
-            (position.x, position.y + size.height as i32),
-            (
-                position.x + size.width as i32,
-                position.y + size.height as i32,
-            ),
+            self.position.x.abs_diff(other.position.x),
+            self.position.y.abs_diff(other.position.y),
+            self.size.width.abs_diff(other.size.width),
+            self.size.height.abs_diff(other.size.height),
         ]
         .into_iter()
-        .any(|(x, y)| x >= left && x < right && y >= top && y < bottom)
+        .max()
+        .unwrap_or_default()
+    }
+}
+
+/// Stops saving `window` if its geometry moved away from `requested`,
+/// see [`Builder::with_detect_wm_override`].
+fn check_wm_override<R: Runtime>(window: &Window<R>, requested: Geometry) {
+    let actual = match Geometry::of(window) {
+        Ok(actual) => actual,
+        Err(_) => return,
+    };
+    if actual.delta(&requested) <= WM_OVERRIDE_THRESHOLD {
+        return;
+    }
+
+    emit_warning(
+        window,
+        Warning::WindowManagerOverride {
+            label: window.label().into(),
+            requested: validation::Rect::new(requested.position, requested.size),
+            actual: validation::Rect::new(actual.position, actual.size),
+        },
+    );
+    let preferences = window.state::<PersistencePreferences>();
+    preferences
+        .0
+        .lock()
+        .unwrap()
+        .entry(window.label().into())
+        .or_insert(false);
+}
+
+/// The name of the event emitted to all windows when a restore changed a window significantly,
+/// see [`Builder::with_restore_change_events`].
+pub const RESTORED_WITH_CHANGES_EVENT: &str = "window-state://restored-with-changes";
+
+/// The name of the event emitted to all windows with a [`WindowStateChange`] while a window
+/// is moved or resized, see [`Builder::with_live_broadcast`].
+pub const STATE_CHANGED_EVENT: &str = "window-state://changed";
+
+/// The payload of [`RESTORED_WITH_CHANGES_EVENT`], with the outer position and
+/// inner size of the window before and after the restore.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoredWithChanges {
+    pub label: String,
+    pub before: validation::Rect,
+    pub after: validation::Rect,
+    /// Why the restored geometry differs from the stored one, if it does.
+    pub adjustments: Vec<Adjustment>,
+}
+
+/// The geometry and modes a window is about to be restored to,
+/// handed to the interceptor set with [`Builder::with_restore_interceptor`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestorePlan {
+    /// The logical inner size to apply, `None` to keep the current size.
+    pub size: Option<LogicalSize<f64>>,
+    /// The physical outer position to apply, `None` to leave the placement to the OS.
+    pub position: Option<PhysicalPosition<i32>>,
+    /// The half or quarter of its monitor to snap the window to after applying the geometry.
+    pub snap: Option<SnapPosition>,
+    /// Whether to maximize the window.
+    pub maximize: bool,
+    /// Whether to make the window fullscreen, `None` to keep its current state.
+    pub fullscreen: Option<bool>,
+    /// How the plan deviates from the stored state, in the order the changes were made.
+    pub adjustments: Vec<Adjustment>,
+}
+
+/// What a restore did, returned by [`WindowExt::restore_state_detailed`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RestoreOutcome {
+    /// Where the applied state came from.
+    pub source: RestoreSource,
+    /// Whether the window was shown.
+    pub shown: bool,
+    /// How the applied state deviates from the stored one, see [`RestorePlan::adjustments`].
+    pub adjustments: Vec<Adjustment>,
+}
+
+/// A geometry stashed with [`WindowExt::stash_state`], to return to with [`WindowExt::restore_stashed`].
+///
+/// Restoring consumes the token, so each stash is restored at most once.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct StateToken(u64);
+
+/// The source of the state a restore applied, see [`RestoreOutcome`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RestoreSource {
+    /// The stored state of the window, loaded from disk or captured earlier in this run.
+    Stored,
+    /// There was no stored state, so the current state of the window was captured instead.
+    Captured,
+    /// The window opened at its default geometry, see [`Builder::with_always_default`].
+    Default,
+    /// Nothing was applied, since the stored state has no geometry yet
+    /// or the interceptor set with [`Builder::with_restore_interceptor`] cancelled the restore.
+    Skipped,
+}
+
+/// A change the plugin made to the stored state while planning a restore, see [`RestorePlan::adjustments`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+#[non_exhaustive]
+pub enum Adjustment {
+    /// The monitor named `stored` the state was saved on isn't connected,
+    /// so the geometry was remapped onto the monitor named `used` instead.
+    MonitorMismatchFallback {
+        stored: String,
+        used: Option<String>,
+    },
+    /// The stored size didn't fit on any monitor and was reduced to fit `monitor`.
+    OversizeReduced {
+        monitor: Option<String>,
+        requested: LogicalSize<f64>,
+        clamped: LogicalSize<f64>,
+    },
+    /// The stored position isn't on any connected monitor, so the OS places the window instead.
+    OffscreenDiscarded,
+    /// The window is snapped to `snap` of its monitor after applying the geometry.
+    Snapped { snap: SnapPosition },
+}
+
+type RestoreInterceptorFn<R> = dyn Fn(&Window<R>, &mut RestorePlan) -> bool + Send + Sync + 'static;
+
+/// The interceptor set with [`Builder::with_restore_interceptor`].
+struct RestoreInterceptor<R: Runtime>(Option<Arc<RestoreInterceptorFn<R>>>);
+
+/// The geometry and modes of a window before a restore, to roll back to with [`Builder::with_atomic_restore`].
+struct WindowSnapshot {
+    geometry: Geometry,
+    maximized: bool,
+    fullscreen: bool,
+    decorated: bool,
+}
+
+impl WindowSnapshot {
+    fn capture(window: &dyn WindowOps) -> tauri::Result<Self> {
+        Ok(Self {
+            geometry: window.geometry()?,
+            maximized: window.is_maximized()?,
+            fullscreen: window.is_fullscreen()?,
+            decorated: window.is_decorated()?,
+        })
+    }
+
+    /// Puts `window` back into the captured state, trying every step even if one fails.
+    fn apply(&self, window: &dyn WindowOps) -> tauri::Result<()> {
+        let results = [
+            window.set_fullscreen(self.fullscreen),
+            match self.maximized {
+                true => Ok(()),
+                false => window.unmaximize(),
+            },
+            window.set_decorations(self.decorated),
+            window.set_physical_size(self.geometry.size),
+            window.set_physical_position(self.geometry.position),
+            match self.maximized {
+                true => window.maximize(),
+                false => Ok(()),
+            },
+        ];
+        results.into_iter().collect()
+    }
+}
+
+/// Runs `restore`, putting `window` back into its state from before if it fails.
+fn with_rollback<T>(
+    window: &dyn WindowOps,
+    restore: impl FnOnce() -> tauri::Result<T>,
+) -> tauri::Result<T> {
+    let snapshot = WindowSnapshot::capture(window)?;
+    restore().map_err(|e| {
+        let _ = snapshot.apply(window);
+        e
+    })
+}
+
+/// A change to the stored state of a window, received through [`AppHandleExt::subscribe_changes`].
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowStateChange {
+    pub label: String,
+    pub state: WindowState,
+}
+
+#[derive(Default)]
+struct Subscribers(Mutex<Vec<Sender<WindowStateChange>>>);
+
+impl Subscribers {
+    fn notify(&self, label: &str, state: &WindowState) {
+        let mut subscribers = self.0.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let change = WindowStateChange {
+            label: label.into(),
+            state: state.clone(),
+        };
+        // sending never blocks, dropped receivers just unsubscribe
+        subscribers.retain(|tx| tx.send(change.clone()).is_ok());
+    }
+}
+
+/// Labels of the windows restored with [`AppHandleExt::restore_before_show`]
+/// whose webview isn't ready yet.
+#[derive(Default)]
+struct RestoredBeforeShow(Mutex<HashSet<String>>);
+
+/// Labels of the windows whose initial restore leaves their size and position alone,
+/// set with [`Builder::with_explicitly_placed`] or [`WindowExt::mark_explicitly_placed`].
+struct ExplicitlyPlaced(Mutex<HashSet<String>>);
+
+/// Windows restored to a default geometry instead of their stored one, set with
+/// [`Builder::with_always_default`], mapped to the size set with [`Builder::with_default_size`].
+struct AlwaysDefault(HashMap<String, Option<LogicalSize<f64>>>);
+
+/// The flags set with [`Builder::with_window_type_flags`], along with the category
+/// of each window set with [`WindowExt::set_window_category`].
+struct WindowCategories {
+    flags: HashMap<String, StateFlags>,
+    tags: Mutex<HashMap<String, String>>,
+}
+
+/// Labels of the windows that had a stored state when the plugin last loaded one,
+/// on startup or with [`replace_state`].
+struct LoadedLabels(Mutex<HashSet<String>>);
+
+/// Labels of the windows that were visible according to the state loaded on startup, sorted.
+struct PreviouslyOpen(Vec<String>);
+
+/// Whether the previous run exited cleanly according to the state loaded on startup.
+struct LastExitClean(Option<bool>);
+
+/// How [`AppHandleExt::restore_all_windows`] arranges windows without a stored state,
+/// set with [`Builder::with_default_tiling`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TilingMode {
+    /// A grid with as many columns as rows, or one more.
+    Grid,
+    /// Side by side columns spanning the monitor height.
+    Columns,
+}
+
+impl TilingMode {
+    /// Splits the rectangle at `position` with `size` into `count` cells.
+    fn cells(
+        &self,
+        position: PhysicalPosition<i32>,
+        size: PhysicalSize<u32>,
+        count: usize,
+    ) -> Vec<(PhysicalPosition<i32>, PhysicalSize<u32>)> {
+        if count == 0 {
+            return Vec::new();
+        }
+
+        let columns = match self {
+            Self::Grid => (count as f64).sqrt().ceil() as usize,
+            Self::Columns => count,
+        };
+        let rows = (count + columns - 1) / columns;
+        let width = size.width / columns as u32;
+        let height = size.height / rows as u32;
+
+        (0..count)
+            .map(|i| {
+                let (column, row) = (i % columns, i / columns);
+                (
+                    PhysicalPosition {
+                        x: position.x + (column as u32 * width) as i32,
+                        y: position.y + (row as u32 * height) as i32,
+                    },
+                    PhysicalSize { width, height },
+                )
+            })
+            .collect()
+    }
+}
+
+/// What restoring does with windows that were hidden when their state was saved,
+/// set with [`Builder::with_hidden_restore_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HiddenPolicy {
+    /// Leaves the window hidden.
+    #[default]
+    KeepHidden,
+    /// Closes the window.
+    Close,
+    /// Shows the window minimized.
+    Minimize,
+}
+
+/// What restoring a layout does with open windows that aren't part of it,
+/// set with [`Builder::with_unknown_in_layout`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UnknownPolicy {
+    /// Leaves the window where it is.
+    #[default]
+    LeaveAsIs,
+    /// Hides the window.
+    Hide,
+    /// Centers the window on its monitor.
+    Center,
+}
+
+/// The format the state file is written in, set with [`Builder::with_format`].
+///
+/// Both formats are read regardless of the format set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StateFormat {
+    /// A compact binary encoding.
+    #[default]
+    Bincode,
+    /// Pretty-printed JSON, for inspecting and hand-editing the state file.
+    Json,
+}
+
+/// The builder configuration needed outside of the plugin hooks.
+struct PluginConfig {
+    state_flags: StateFlags,
+    proportional_remap: bool,
+    default_tiling: Option<TilingMode>,
+    primary_relative_coords: bool,
+    fit_before_maximize: bool,
+    hidden_policy: HiddenPolicy,
+    unknown_in_layout: UnknownPolicy,
+    restore_change_threshold: Option<u32>,
+    atomic_restore: bool,
+    parallel_restore: bool,
+    percentage_positions: bool,
+    continue_on_restore_error: bool,
+    detect_wm_override: bool,
+    excluded_monitors: HashSet<String>,
+    maximize_detection_threshold: Option<f64>,
+    format: StateFormat,
+    annotations: bool,
+    /// Whether restores show windows that were visible, see [`AppHandleExt::set_auto_show`].
+    auto_show: AtomicBool,
+}
+
+impl PluginConfig {
+    fn restore_options(&self) -> RestoreOptions {
+        RestoreOptions {
+            proportional_remap: self.proportional_remap,
+            percentage_positions: self.percentage_positions,
+            fit_before_maximize: self.fit_before_maximize,
+            continue_on_error: self.continue_on_restore_error,
+        }
+    }
+}
+
+/// The parts of [`PluginConfig`] planning and applying a restore depend on.
+#[derive(Debug, Clone, Copy, Default)]
+struct RestoreOptions {
+    proportional_remap: bool,
+    percentage_positions: bool,
+    fit_before_maximize: bool,
+    continue_on_error: bool,
+}
+
+/// The version of the [`PersistedState`] envelope written by this version of the plugin.
+const STATE_VERSION: u32 = 1;
+
+/// Data stored in the state file next to the per-window states.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+struct PersistedMetadata {
+    /// The window that had focus last.
+    focused_label: Option<String>,
+    /// The origin of the primary monitor the stored positions are relative to,
+    /// with [`Builder::with_primary_relative_coords`] set.
+    primary_origin: Option<PhysicalPosition<i32>>,
+    /// The layouts saved with [`AppHandleExt::save_to_slot`].
+    #[serde(default, serialize_with = "serialize_sorted_slots")]
+    slots: BTreeMap<u8, HashMap<String, WindowState>>,
+    /// The app version the state was last loaded with, see [`Builder::with_reset_on_version_change`].
+    #[serde(default)]
+    app_version: Option<String>,
+    /// Whether the app saved the state on exit: `false` while it runs, `true` once it exited
+    /// cleanly, `None` if the state was written before this was recorded.
+    #[serde(default)]
+    clean_exit: Option<bool>,
+    /// The window that had focus last on each monitor, by monitor name.
+    #[serde(default)]
+    focused_per_monitor: BTreeMap<String, String>,
+}
+
+/// The number of layout slots, numbered from `0`.
+pub const MAX_SLOTS: u8 = 10;
+
+/// Moves all stored positions into the coordinate space where the primary monitor is at `origin`.
+fn rebase_positions(
+    state: &mut HashMap<String, WindowState>,
+    metadata: &mut PersistedMetadata,
+    origin: PhysicalPosition<i32>,
+) {
+    if let Some(saved) = metadata.primary_origin {
+        if saved != origin {
+            for s in state.values_mut() {
+                s.translate(origin.x - saved.x, origin.y - saved.y);
+            }
+        }
+    }
+    metadata.primary_origin = Some(origin);
+}
+
+struct Metadata(Mutex<PersistedMetadata>);
+
+/// The content of the state file.
+#[derive(Deserialize)]
+struct PersistedState {
+    version: u32,
+    windows: HashMap<String, WindowState>,
+    metadata: PersistedMetadata,
+}
+
+/// [`PersistedState`] borrowing its data, to save it without cloning the window states.
+#[derive(Serialize)]
+struct PersistedStateRef<'a> {
+    version: u32,
+    #[serde(serialize_with = "serialize_sorted")]
+    windows: &'a HashMap<String, WindowState>,
+    metadata: &'a PersistedMetadata,
+}
+
+impl PersistedState {
+    /// Wraps a flat map of window states, as written before the envelope existed.
+    fn from_windows(windows: HashMap<String, WindowState>) -> Self {
+        Self {
+            version: 0,
+            windows,
+            metadata: Default::default(),
+        }
+    }
+}
+
+/// The largest state the plugin decodes, bounding the memory a corrupt or crafted state can allocate.
+const MAX_STATE_BYTES: usize = 16 * 1024 * 1024;
+/// The most window states loaded at once, further states are rejected.
+const MAX_WINDOW_STATES: usize = 1024;
+/// The largest logical width and height restored, larger sizes are clamped.
+const MAX_WINDOW_SIZE: f64 = 100_000.;
+/// The largest absolute physical coordinate restored, positions further out are clamped.
+const MAX_COORDINATE: i32 = 1_000_000;
+
+/// Decodes a state file, accepting both the bincode format the plugin writes
+/// and a JSON object of the same shape, with or without the [`PersistedState`] envelope.
+///
+/// Flat maps, including the layout written before [`WindowState`] gained fields beyond the
+/// geometry, are wrapped as version 0 and written in the current format on the next save.
+/// Unknown fields in the JSON form, like the `_comment` and `_note` fields written with
+/// [`Builder::with_annotations`] or added while hand-editing, are ignored. A flat map has
+/// no such fields: every key is a label, so a `_comment` key fails to decode as a window
+/// state and the whole file is rejected.
+fn deserialize_state(bytes: &[u8]) -> Result<PersistedState> {
+    if bytes.len() > MAX_STATE_BYTES {
+        return Err(Error::StateTooLarge(bytes.len()));
+    }
+
+    let looks_like_json = bytes
+        .iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .map_or(false, |b| *b == b'{');
+
+    if looks_like_json {
+        if let Ok(state) = serde_json::from_slice(bytes) {
+            return Ok(state);
+        }
+        if let Ok(windows) = serde_json::from_slice(bytes) {
+            return Ok(PersistedState::from_windows(windows));
+        }
+    }
+
+    // reject trailing bytes, so a flat map is never mistaken for a prefix that happens
+    // to decode as an envelope, and the other way around
+    bincode_exact::<PersistedState>(bytes)
+        .map(|state| {
+            if state.version > STATE_VERSION {
+                log::warn!(
+                    "window state was saved by a newer version of the plugin (version {})",
+                    state.version
+                );
+            }
+            state
+        })
+        .or_else(|e| {
+            bincode_exact(bytes)
+                .map(PersistedState::from_windows)
+                .map_err(|_| e)
+        })
+        .or_else(|e| {
+            bincode_exact::<HashMap<String, LegacyWindowState>>(bytes)
+                .map(|legacy| {
+                    PersistedState::from_windows(
+                        legacy.into_iter().map(|(l, s)| (l, s.into())).collect(),
+                    )
+                })
+                .map_err(|_| e)
+        })
+        .map_err(Into::into)
+}
+
+/// Decodes `bytes` like [`bincode::deserialize`], but fails unless they are consumed entirely.
+fn bincode_exact<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> bincode::Result<T> {
+    use bincode::Options;
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(bytes)
+}
+
+/// The key the state of `route` of the window `label` is stored under.
+///
+/// Window labels can't contain `@`, so route keys never collide with labels.
+fn route_key(label: &str, route: &str) -> String {
+    format!("{}@{}", label, route)
+}
+
+/// The label of the window a state key belongs to.
+fn key_label(key: &str) -> &str {
+    key.split('@').next().unwrap_or(key)
+}
+
+/// Brings the loaded `state` into the range the plugin can restore, rejecting malformed
+/// entries and the entries beyond [`MAX_WINDOW_STATES`] with [`Warning::RejectedState`].
+///
+/// The state file may come from an untrusted source, for example when it is synced.
+fn sanitize_state<R: Runtime, M: Manager<R>>(manager: &M, state: &mut PersistedState) {
+    for warning in sanitize_windows(state) {
+        emit_warning(manager, warning);
+    }
+}
+
+/// The part of [`sanitize_state`] that doesn't need the app, returning the warnings to emit.
+fn sanitize_windows(state: &mut PersistedState) -> Vec<Warning> {
+    let mut warnings = sanitize_entries(&mut state.windows);
+    state.metadata.slots.retain(|slot, _| *slot < MAX_SLOTS);
+    // restoring a slot puts its entries into the cache as they are, so they get the same checks
+    for (slot, windows) in state.metadata.slots.iter_mut() {
+        warnings.extend(
+            sanitize_entries(windows)
+                .into_iter()
+                .map(|warning| match warning {
+                    Warning::RejectedState { label, reason } => Warning::RejectedState {
+                        label,
+                        reason: format!("{} in slot {}", reason, slot),
+                    },
+                    warning => warning,
+                }),
+        );
+    }
+    warnings
+}
+
+/// Sanitizes the window states of a layout for [`sanitize_windows`].
+fn sanitize_entries(windows: &mut HashMap<String, WindowState>) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut labels: Vec<String> = windows.keys().cloned().collect();
+    labels.sort();
+    for (i, label) in labels.into_iter().enumerate() {
+        let window = windows.get_mut(&label).unwrap();
+        let reason = if i >= MAX_WINDOW_STATES {
+            Some(format!("more than {} window states", MAX_WINDOW_STATES))
+        } else if !window.width.is_finite()
+            || !window.height.is_finite()
+            || window.width < 0.
+            || window.height < 0.
+        {
+            Some(format!("invalid size {}x{}", window.width, window.height))
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            windows.remove(&label);
+            warnings.push(Warning::RejectedState { label, reason });
+            continue;
+        }
+
+        window.width = window.width.min(MAX_WINDOW_SIZE);
+        window.height = window.height.min(MAX_WINDOW_SIZE);
+        for coordinate in [
+            &mut window.x,
+            &mut window.y,
+            &mut window.prev_x,
+            &mut window.prev_y,
+        ] {
+            *coordinate = (*coordinate).clamp(-MAX_COORDINATE, MAX_COORDINATE);
+        }
+        // the monitor is only used to remap the geometry, so a malformed one is just dropped
+        if window.monitor.as_ref().map_or(false, |m| {
+            !(m.scale_factor.is_finite() && m.scale_factor > 0.) || m.width == 0 || m.height == 0
+        }) {
+            window.monitor = None;
+        }
+        // like the monitor, the fractions and the zoom are optional and dropped if non-finite
+        if window.fractions.map_or(false, |f| {
+            ![f.x, f.y, f.width, f.height].iter().all(|v| v.is_finite())
+        }) {
+            window.fractions = None;
+        }
+        if window
+            .zoom
+            .map_or(false, |zoom| !(zoom.is_finite() && zoom > 0.))
+        {
+            window.zoom = None;
+        }
+    }
+    warnings
+}
+
+/// Serializes `windows` sorted by label, so the same state always encodes to the same bytes.
+fn serialize_sorted<S: Serializer>(
+    windows: &&HashMap<String, WindowState>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    windows
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+/// Serializes the layout slots with the windows of each sorted by label, like [`serialize_sorted`].
+fn serialize_sorted_slots<S: Serializer>(
+    slots: &BTreeMap<u8, HashMap<String, WindowState>>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    slots
+        .iter()
+        .map(|(slot, windows)| (slot, windows.iter().collect::<BTreeMap<_, _>>()))
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+/// Writes a state encoded with [`encode_state`] to `path`.
+fn write_state_to(path: &Path, bytes: &[u8]) -> Result<()> {
+    File::create(path)
+        .and_then(|mut f| f.write_all(bytes))
+        .map_err(Into::into)
+}
+
+/// Copies the first of `legacy` that exists to `path` unless a file is already there,
+/// returning the legacy path that was copied, see [`Builder::with_legacy_paths`].
+fn migrate_legacy_state(path: &Path, legacy: &[PathBuf]) -> Result<Option<PathBuf>> {
+    if path.exists() {
+        return Ok(None);
+    }
+    let source = match legacy.iter().find(|p| p.is_file()) {
+        Some(source) => source,
+        None => return Ok(None),
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // copy next to the destination first, so an interrupted copy never leaves a partial state file
+    let tmp = tmp_path(path);
+    std::fs::copy(source, &tmp)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(Some(source.clone()))
+}
+
+/// Removes the state file at `path` along with the `.tmp` sibling an interrupted
+/// write may leave behind, ignoring the ones that don't exist.
+fn remove_state_files(path: &Path) -> Result<()> {
+    if path.file_name().is_none() {
+        return Ok(());
+    }
+
+    for path in [path.to_path_buf(), tmp_path(path)] {
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The store the state is persisted to, `None` if there is nowhere to persist it.
+struct Store(Mutex<Option<Box<dyn StateStore>>>);
+
+/// Reads and decodes the state saved in `store`.
+fn read_state(store: &dyn StateStore) -> Result<PersistedState> {
+    match store.load()? {
+        Some(bytes) => deserialize_state(&bytes),
+        None => Ok(PersistedState {
+            version: STATE_VERSION,
+            windows: Default::default(),
+            metadata: Default::default(),
+        }),
+    }
+}
+
+/// Encodes the given cache and the stored metadata the way they are persisted.
+fn encode_state<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &HashMap<String, WindowState>,
+) -> Result<Vec<u8>> {
+    let config = app.state::<PluginConfig>();
+    let metadata = app.state::<Metadata>();
+    let metadata = metadata.0.lock().unwrap();
+    encode_persisted(state, &metadata, config.format, config.annotations)
+}
+
+/// Encodes `state` and `metadata` in the format of the state file,
+/// with the notes of [`Builder::with_annotations`] if `annotations` is set.
+fn encode_persisted(
+    state: &HashMap<String, WindowState>,
+    metadata: &PersistedMetadata,
+    format: StateFormat,
+    annotations: bool,
+) -> Result<Vec<u8>> {
+    let persisted = PersistedStateRef {
+        version: STATE_VERSION,
+        windows: state,
+        metadata,
+    };
+    match format {
+        StateFormat::Bincode => bincode::serialize(&persisted).map_err(Error::Bincode),
+        StateFormat::Json if !annotations => {
+            serde_json::to_vec_pretty(&persisted).map_err(Into::into)
+        }
+        StateFormat::Json => {
+            let mut value = serde_json::to_value(&persisted)?;
+            annotate(&mut value, state);
+            serde_json::to_vec_pretty(&value).map_err(Into::into)
+        }
+    }
+}
+
+/// Adds the `_comment` and `_note` fields of [`Builder::with_annotations`] to a JSON encoded state.
+fn annotate(value: &mut serde_json::Value, state: &HashMap<String, WindowState>) {
+    let envelope = match value.as_object_mut() {
+        Some(envelope) => envelope,
+        None => return,
+    };
+    envelope.insert(
+        "_comment".into(),
+        "Window state of tauri-plugin-window-state. Fields starting with `_` are ignored when loading and rewritten on save."
+            .into(),
+    );
+    let windows = match envelope.get_mut("windows").and_then(|w| w.as_object_mut()) {
+        Some(windows) => windows,
+        None => return,
+    };
+    for (label, s) in state {
+        if let Some(window) = windows.get_mut(label).and_then(|w| w.as_object_mut()) {
+            let mut note = format!("{}x{} at {},{}", s.width, s.height, s.x, s.y);
+            if let Some(name) = s.monitor.as_ref().and_then(|m| m.name.as_ref()) {
+                note.push_str(&format!(" on monitor {}", name));
+            }
+            if s.maximized {
+                note.push_str(", maximized");
+            }
+            window.insert("_note".into(), note.into());
+        }
+    }
+}
+
+/// Writes the given cache to the configured [`StateStore`].
+fn write_state<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &HashMap<String, WindowState>,
+) -> Result<()> {
+    if let Some(store) = &*app.state::<Store>().0.lock().unwrap() {
+        let bytes = encode_state(app, state)?;
+        store.save(&bytes)?;
+        if let Some(path) = store.path() {
+            app.state::<OnSave>().notify(path, bytes);
+        }
+    }
+    Ok(())
+}
+
+type OnSaveFn = dyn Fn(&Path, usize) + Send + Sync + 'static;
+
+/// The callback set with [`Builder::with_on_save`], along with the last bytes it was called for.
+struct OnSave(Option<Box<OnSaveFn>>, Mutex<Option<Vec<u8>>>);
+
+impl OnSave {
+    /// Calls the callback for `bytes` written to `path`, unless they didn't change since the last call.
+    fn notify(&self, path: &Path, bytes: Vec<u8>) {
+        if let Some(on_save) = &self.0 {
+            let mut last = self.1.lock().unwrap();
+            if last.as_ref() != Some(&bytes) {
+                on_save(path, bytes.len());
+                last.replace(bytes);
+            }
+        }
+    }
+}
+
+/// Replaces the given cache, the stored metadata and the [`LoadedLabels`] with `loaded`,
+/// keeping open windows tracked even if `loaded` has no state for them.
+fn replace_state<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &mut HashMap<String, WindowState>,
+    loaded: PersistedState,
+) {
+    let mut windows = loaded.windows;
+    *app.state::<LoadedLabels>().0.lock().unwrap() = windows.keys().cloned().collect();
+    for label in state.keys() {
+        if app.get_window(label).is_some() {
+            windows.entry(label.clone()).or_default();
+        }
+    }
+    *state = windows;
+    *app.state::<Metadata>().0.lock().unwrap() = loaded.metadata;
+}
+
+/// The properties of an open window its [`WindowState`] is updated from.
+///
+/// The properties are read without holding any lock of the plugin, see [`update_from_windows`],
+/// and merged into the cached state afterwards with [`merge_properties`].
+#[derive(Debug, Clone, Default)]
+struct WindowProperties {
+    /// Whether the window is maximized or, with [`Builder::with_maximize_detection_threshold`],
+    /// fills its monitor. Only read for [`StateFlags::MAXIMIZED`] and [`StateFlags::SIZE`].
+    maximized: bool,
+    minimized: bool,
+    /// The name of the monitor the window is maximized on.
+    maximized_monitor: Option<String>,
+    fullscreen: bool,
+    decorated: bool,
+    visible: bool,
+    /// The logical inner size.
+    size: LogicalSize<f64>,
+    /// The physical outer position.
+    position: PhysicalPosition<i32>,
+    monitor: Option<MonitorInfo>,
+    /// The names of the monitors the window overlaps, if more than one.
+    spanned_monitors: Vec<String>,
+    theme: Option<Theme>,
+    snap: Option<SnapPosition>,
+    /// The outer position of the window set with [`WindowExt::set_relative_to`], if it's open.
+    parent_origin: Option<PhysicalPosition<i32>>,
+}
+
+/// Updates `state` with the `properties` that `flags` track.
+///
+/// The normal geometry is kept while the window is maximized or minimized,
+/// so it's restored when the window returns to its normal state.
+fn merge_properties(
+    state: &mut WindowState,
+    properties: &WindowProperties,
+    flags: StateFlags,
+    percentage_positions: bool,
+) {
+    let normal = !properties.maximized && !properties.minimized;
+
+    if flags.contains(StateFlags::MAXIMIZED) {
+        state.maximized = properties.maximized;
+        state.maximized_monitor = properties.maximized_monitor.clone();
+    }
+
+    if flags.contains(StateFlags::FULLSCREEN) {
+        // the runtime can't tell the modes apart, so keep the mode the app restored
+        state.fullscreen = match (properties.fullscreen, state.fullscreen) {
+            (true, FullscreenMode::Exclusive) => FullscreenMode::Exclusive,
+            (fullscreen, _) => fullscreen.into(),
+        };
+    }
+
+    if flags.contains(StateFlags::DECORATIONS) {
+        state.decorated = properties.decorated;
+    }
+
+    if flags.contains(StateFlags::VISIBLE) {
+        state.visible = properties.visible;
+        state.minimized = properties.minimized;
+    }
+
+    // It doesn't make sense to save a window with 0 height or width,
+    // the other properties of a minimized window reporting one are still saved
+    if flags.contains(StateFlags::SIZE)
+        && normal
+        && properties.size.width > 0.
+        && properties.size.height > 0.
+    {
+        state.width = properties.size.width;
+        state.height = properties.size.height;
+    }
+
+    if flags.contains(StateFlags::POSITION) && normal {
+        state.x = properties.position.x;
+        state.y = properties.position.y;
+        if let (Some(origin), Some(relative)) =
+            (properties.parent_origin, state.relative_to.as_mut())
+        {
+            relative.offset = PhysicalPosition::new(
+                properties.position.x - origin.x,
+                properties.position.y - origin.y,
+            );
+        }
+    }
+
+    if flags.intersects(StateFlags::SIZE | StateFlags::POSITION) && normal {
+        state.monitor = properties.monitor.clone();
+        state.spanned_monitors = properties.spanned_monitors.clone();
+    }
+
+    if flags.contains(StateFlags::THEME) {
+        state.theme = properties.theme;
+    }
+
+    if percentage_positions && normal {
+        state.fractions = state.monitor_fractions();
+    }
+
+    if flags.contains(StateFlags::SNAP) && normal && !properties.fullscreen {
+        state.snap = properties.snap;
+    }
+}
+
+/// Updates `state` from the open windows, using `flags` for all of them or the flags
+/// configured for each window if `None`, and returns the number of updated windows.
+///
+/// Windows inside a [`WindowExt::begin_programmatic`] bracket, with persistence turned off
+/// or with saving paused are skipped.
+///
+/// Window getters called off the main thread wait for the event loop, whose event handlers
+/// lock the cache, so the windows are read without holding any lock of the plugin
+/// and the cache is only locked again to merge what was read.
+fn update_from_windows<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    flags: Option<StateFlags>,
+) -> tauri::Result<usize> {
+    let cache = app.state::<WindowStateCache>();
+    // the labels are collected first, so no lock is held while the windows are read
+    let (labels, tracked) = {
+        let state = cache.0.lock().unwrap();
+        let programmatic = app.state::<ProgrammaticWindows>();
+        let programmatic = programmatic.0.lock().unwrap();
+        let preferences = app.state::<PersistencePreferences>();
+        let preferences = preferences.0.lock().unwrap();
+        let paused = app.state::<SavePaused>();
+
+        let labels: Vec<String> = state.keys().cloned().collect();
+        let tracked: Vec<(String, Option<String>)> = state
+            .iter()
+            .filter(|(label, _)| {
+                !programmatic.contains_key(*label)
+                    && preferences.get(*label) != Some(&false)
+                    && !paused.is_paused(label)
+            })
+            .map(|(label, s)| {
+                let parent = s.relative_to.as_ref().map(|r| r.parent.clone());
+                (label.clone(), parent)
+            })
+            .collect();
+        (labels, tracked)
+    };
+
+    let origin = match labels.iter().find_map(|label| app.get_window(label)) {
+        Some(window) => window.primary_origin()?,
+        None => None,
+    };
+    let mut read = Vec::new();
+    for (label, parent) in tracked {
+        if let Some(window) = app.get_window(&label) {
+            let flags = flags.unwrap_or_else(|| window.tracked_flags());
+            let properties = window.read_properties(flags, parent.as_deref())?;
+            read.push((label, flags, properties));
+        }
+    }
+
+    let percentage_positions = app.state::<PluginConfig>().percentage_positions;
+    let mut state = cache.0.lock().unwrap();
+    if let Some(origin) = origin {
+        let metadata = app.state::<Metadata>();
+        rebase_positions(&mut state, &mut metadata.0.lock().unwrap(), origin);
+    }
+    let mut updated = 0;
+    for (label, flags, properties) in &read {
+        if let Some(s) = state.get_mut(label) {
+            merge_properties(s, properties, *flags, percentage_positions);
+            updated += 1;
+        }
+    }
+    Ok(updated)
+}
+
+/// Moves the state of `from` to `to` for [`AppHandleExt::rename_window_state`].
+fn rename_entry(
+    state: &mut HashMap<String, WindowState>,
+    from: &str,
+    to: &str,
+    overwrite: bool,
+) -> Result<()> {
+    if !state.contains_key(from) {
+        return Err(Error::StateNotFound(from.into()));
+    }
+    // renaming a state to its own label would otherwise fail as a conflict with itself
+    if from == to {
+        return Ok(());
+    }
+    if !overwrite && state.contains_key(to) {
+        return Err(Error::StateAlreadyExists(to.into()));
+    }
+
+    let s = state.remove(from).unwrap();
+    state.insert(to.into(), s);
+    Ok(())
+}
+
+/// Saves all open windows state with the flags configured for each window.
+fn save_tracked_state<R: Runtime>(app: &tauri::AppHandle<R>) -> Result<()> {
+    update_from_windows(app, None)?;
+    let cache = app.state::<WindowStateCache>();
+    let state = cache.0.lock().unwrap();
+    write_state(app, &state)
+}
+
+pub trait AppHandleExt {
+    /// The runtime of the windows this trait creates.
+    type Runtime: Runtime;
+
+    /// Saves all open windows state to disk
+    fn save_window_state(&self, flags: StateFlags) -> Result<()>;
+    /// Moves the stored state of the window `from` to the label `to` and saves it to disk.
+    ///
+    /// Useful to migrate saved layouts when an app changes its window labels.
+    /// Fails if there is no state for `from`, or if `to` already has a state and `overwrite` is `false`.
+    /// Renaming a state to its own label does nothing.
+    fn rename_window_state(&self, from: &str, to: &str, overwrite: bool) -> Result<()>;
+    /// Writes the current state of all windows to `path`, without touching the state file.
+    ///
+    /// The file uses the same format as the state file, including the layout slots saved with
+    /// [`AppHandleExt::save_to_slot`], and can be shared or kept in version control.
+    fn export_layout_to_file(&self, path: &Path) -> Result<()>;
+    /// Loads a layout written by [`AppHandleExt::export_layout_to_file`] into the in-memory state.
+    ///
+    /// Both the bincode and the JSON format are accepted. In the JSON format, fields the plugin
+    /// doesn't know, like a `_comment` next to the envelope's `version` or a `_note` in a window
+    /// state, are ignored, so hand-edited layouts can carry notes. Imported entries replace the
+    /// stored state of windows with the same label and imported layout slots replace the slots
+    /// with the same number; call [`WindowExt::restore_state`] to apply them and
+    /// [`AppHandleExt::save_window_state`] to persist them. The rest of the file's metadata,
+    /// like the focused window, describes the session that wrote it and isn't imported.
+    fn import_layout_from_file(&self, path: &Path) -> Result<()>;
+    /// Loads the layout at `path` as the starting state if no window state is stored yet,
+    /// for example a factory layout shipped as a resource of the app bundle.
+    ///
+    /// Accepts the same formats as [`AppHandleExt::import_layout_from_file`]: a JSON object
+    /// mapping labels to states, with or without the state file envelope, or the bincode format
+    /// of the state file. Does nothing if any window already has a stored geometry, so the
+    /// file is only read on the first run. The file isn't written to, changes are saved to
+    /// the state file as usual. Call it from the app's `setup`, before windows get restored.
+    fn seed_from_file_if_empty(&self, path: &Path) -> Result<()>;
+    /// Sets whether [`WindowExt::restore_state`], including the initial restore of
+    /// windows created later, shows and focuses windows that were visible when their
+    /// state was saved, for apps with a "restore window visibility" preference.
+    ///
+    /// This only affects later restores, already restored windows are neither shown nor hidden.
+    /// Methods taking an explicit `auto_show` argument ignore it. Defaults to `true`.
+    fn set_auto_show(&self, auto_show: bool);
+    /// Whether restores show windows that were visible, see [`AppHandleExt::set_auto_show`].
+    fn auto_show(&self) -> bool;
+    /// Saves the current state of all open windows to the layout slot `slot`,
+    /// replacing the layout saved there before, for example from a hotkey.
+    ///
+    /// There are [`MAX_SLOTS`] slots, numbered from `0`. The slots are stored in the state file,
+    /// which is written right away, and only contain the windows open when saving.
+    fn save_to_slot(&self, slot: u8) -> Result<()>;
+    /// Restores the layout saved to `slot` with [`AppHandleExt::save_to_slot`] onto all open windows,
+    /// using the flags set with [`Builder::with_state_flags`].
+    ///
+    /// Windows in the slot that aren't open have their stored state replaced as well,
+    /// so the layout applies once the app creates them. Open windows missing from the slot
+    /// are handled as set with [`Builder::with_unknown_in_layout`].
+    /// Fails with [`Error::SlotEmpty`] if nothing was saved to `slot`.
+    fn restore_from_slot(&self, slot: u8) -> Result<()>;
+    /// Calls `f` with the label and stored state of every window, without cloning the state.
+    ///
+    /// The closure runs while the state lock is held, so it should be quick
+    /// and must not call back into the plugin APIs.
+    fn for_each_window_state(&self, f: impl FnMut(&str, &WindowState));
+    /// Returns whether the window `label` was visible when its state was last stored,
+    /// or `None` if there is no stored state for it.
+    ///
+    /// Lets apps decide at startup whether to recreate a window the user had closed.
+    fn was_window_visible(&self, label: &str) -> Option<bool>;
+    /// Returns the labels of the windows that were visible according to the state loaded
+    /// on startup, sorted, so the app can recreate the previous session.
+    ///
+    /// Unlike [`AppHandleExt::was_window_visible`], this reflects the state file as it was loaded,
+    /// not later changes. The app is responsible for creating the windows.
+    fn previously_open_labels(&self) -> Vec<String>;
+    /// Stores the current state of all open windows like [`WindowExt::capture_state`],
+    /// without saving it to disk, and returns the number of windows captured.
+    ///
+    /// Pair it with [`AppHandleExt::commit_layout`] for a "remember this layout" action
+    /// the user confirms before it is persisted.
+    fn capture_layout(&self) -> Result<usize>;
+    /// Saves the stored state to disk as it is, without updating it from the open windows first,
+    /// for example to persist a layout taken with [`AppHandleExt::capture_layout`].
+    fn commit_layout(&self) -> Result<()>;
+    /// Returns whether the previous run of the app saved the window state on exit,
+    /// so the app can offer to recover from a crash when it didn't.
+    ///
+    /// The plugin marks the state file right after loading it on startup and again on a clean exit.
+    /// `None` if there was no state file, or it was written by a version of the plugin
+    /// that didn't record this. If the startup mark can't be written, for example because
+    /// the directory is read-only, the next launch reports the run before it instead.
+    fn last_exit_was_clean(&self) -> Option<bool>;
+    /// Forgets the stored state of all windows and saves the empty state to disk.
+    ///
+    /// Windows are placed by the app and the OS again on the next restore or launch.
+    fn clear_all_window_states(&self) -> Result<()>;
+    /// Forgets the stored state of all windows, along with the slots, the focused windows,
+    /// the recorded app version and clean exit, and removes the state file from disk,
+    /// for apps that clear all their data.
+    ///
+    /// Unlike [`AppHandleExt::clear_all_window_states`], this doesn't write an empty file.
+    /// Next to the state file, it removes the `.tmp` file an interrupted write may
+    /// have left behind, and succeeds if neither exists. Does nothing on disk
+    /// for stores not backed by a file. Automatic saves, like the one on exit,
+    /// write the state file again unless the app exits right away.
+    fn delete_state_file(&self) -> Result<()>;
+    /// Restores the state of all open windows tracked by the plugin.
+    ///
+    /// With [`Builder::with_default_tiling`] set, windows that had no stored state on startup
+    /// are arranged into tiles across the monitor of the first such window instead.
+    /// Combine it with [`Builder::skip_initial_state`] to restore the windows in one batch.
+    ///
+    /// Afterwards the window that had focus last on each monitor is focused, bringing it to the front
+    /// of that monitor, and then the window that had focus last overall, which ends up with keyboard focus.
+    /// This is best-effort and only meaningful when several windows are restored together:
+    /// Windows and macOS may refuse to move focus while the app isn't in the foreground,
+    /// and on Linux it depends on the focus stealing prevention of the window manager.
+    ///
+    /// With [`Builder::with_parallel_restore`] set, the windows are restored concurrently
+    /// unless this is called on the thread running the event loop.
+    fn restore_all_windows(&self, flags: StateFlags) -> tauri::Result<()>;
+    /// Switches the file the state is saved to, for example when the logged-in user changes,
+    /// and reloads the stored state from it.
+    ///
+    /// When `flush` is `true` the current state is saved to the previous location first,
+    /// otherwise unsaved changes are discarded. This replaces any store set with [`Builder::with_store`].
+    fn set_state_path(&self, path: PathBuf, flush: bool) -> Result<()>;
+    /// Removes the stored state of every window whose label doesn't satisfy `keep`
+    /// and saves the result to disk, returning the number of removed entries.
+    fn compact_state(&self, keep: impl Fn(&str) -> bool) -> Result<usize>;
+    /// Removes the stored state of every window that isn't currently open, along with its
+    /// route states, and saves the result to disk, returning the number of removed entries.
+    fn compact_to_current_windows(&self) -> Result<usize>;
+    /// Returns a channel receiving the new state of a window each time the plugin
+    /// updates it in response to a window event. Drop the receiver to unsubscribe.
+    fn subscribe_changes(&self) -> Receiver<WindowStateChange>;
+    /// Returns the state of all windows encoded exactly as the plugin persists it,
+    /// to embed it in storage the app owns.
+    fn serialize_state(&self) -> Result<Vec<u8>>;
+    /// Replaces the stored state with `bytes` produced by [`AppHandleExt::serialize_state`],
+    /// without saving it to disk. Call [`WindowExt::restore_state`] to apply it.
+    fn deserialize_state(&self, bytes: &[u8]) -> Result<()>;
+    /// Restores the state of the window `label` without showing it, so a window created
+    /// with `visible: false` appears in its restored place once the app shows it.
+    ///
+    /// Call this in the app's `setup`. The plugin then skips its own restore once the
+    /// window's webview is ready, leaving the window hidden until the app shows it.
+    /// Does nothing if there is no window `label`.
+    fn restore_before_show(&self, label: &str) -> tauri::Result<()>;
+    /// Creates a window from `config`, restoring its state before it gets shown,
+    /// so the window never appears at its default position.
+    ///
+    /// The window is created hidden and its state restored with the flags set with
+    /// [`Builder::with_state_flags`]. It is then shown and focused if `config.visible` is `true`,
+    /// regardless of the stored visibility. Every other field of `config` is passed
+    /// through to [`tauri::WindowBuilder::from_config`] as is.
+    fn create_restored_window(&self, config: WindowConfig) -> tauri::Result<Window<Self::Runtime>>;
+}
+
+impl<R: Runtime> AppHandleExt for tauri::AppHandle<R> {
+    type Runtime = R;
+
+    fn save_window_state(&self, flags: StateFlags) -> Result<()> {
+        update_from_windows(self, Some(flags))?;
+        let cache = self.state::<WindowStateCache>();
+        let state = cache.0.lock().unwrap();
+        write_state(self, &state)
+    }
+
+    fn rename_window_state(&self, from: &str, to: &str, overwrite: bool) -> Result<()> {
+        let cache = self.state::<WindowStateCache>();
+        let mut state = cache.0.lock().unwrap();
+        rename_entry(&mut state, from, to, overwrite)?;
+        write_state(self, &state)
+    }
+
+    fn export_layout_to_file(&self, path: &Path) -> Result<()> {
+        update_from_windows(self, None)?;
+        let cache = self.state::<WindowStateCache>();
+        let state = cache.0.lock().unwrap();
+        write_state_to(path, &encode_state(self, &state)?)
+    }
+
+    fn import_layout_from_file(&self, path: &Path) -> Result<()> {
+        let mut layout = tauri::api::file::read_binary(path)
+            .map_err(Error::TauriApi)
+            .and_then(|bytes| deserialize_state(&bytes))?;
+        sanitize_state(self, &mut layout);
+        let cache = self.state::<WindowStateCache>();
+        cache.0.lock().unwrap().extend(layout.windows);
+        self.state::<Metadata>()
+            .0
+            .lock()
+            .unwrap()
+            .slots
+            .extend(layout.metadata.slots);
+        Ok(())
+    }
+
+    fn set_auto_show(&self, auto_show: bool) {
+        self.state::<PluginConfig>()
+            .auto_show
+            .store(auto_show, Ordering::Relaxed);
+    }
+
+    fn auto_show(&self) -> bool {
+        self.state::<PluginConfig>()
+            .auto_show
+            .load(Ordering::Relaxed)
+    }
+
+    fn seed_from_file_if_empty(&self, path: &Path) -> Result<()> {
+        let cache = self.state::<WindowStateCache>();
+        let is_empty =
+            |state: &HashMap<String, WindowState>| state.values().all(|s| s.geometry_unset());
+        if !is_empty(&cache.0.lock().unwrap()) {
+            return Ok(());
+        }
+
+        // the file is read and sanitized, which may emit warnings, before locking the cache
+        let mut layout = tauri::api::file::read_binary(path)
+            .map_err(Error::TauriApi)
+            .and_then(|bytes| deserialize_state(&bytes))?;
+        sanitize_state(self, &mut layout);
+
+        let mut state = cache.0.lock().unwrap();
+        // a window may have been saved while the file was read
+        if !is_empty(&state) {
+            return Ok(());
+        }
+        for (label, seeded) in layout.windows {
+            // keep data the app stored for windows without a geometry
+            let user_data = state.get(&label).and_then(|s| s.user_data.clone());
+            state.insert(
+                label,
+                WindowState {
+                    user_data: user_data.or(seeded.user_data),
+                    ..seeded
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn save_to_slot(&self, slot: u8) -> Result<()> {
+        if slot >= MAX_SLOTS {
+            return Err(Error::InvalidSlot(slot));
+        }
+        update_from_windows(self, None)?;
+        let cache = self.state::<WindowStateCache>();
+        let state = cache.0.lock().unwrap();
+        let layout = state
+            .iter()
+            .filter(|(key, _)| self.get_window(key).is_some())
+            .map(|(key, s)| (key.clone(), s.clone()))
+            .collect();
+        self.state::<Metadata>()
+            .0
+            .lock()
+            .unwrap()
+            .slots
+            .insert(slot, layout);
+        write_state(self, &state)
+    }
+
+    fn restore_from_slot(&self, slot: u8) -> Result<()> {
+        if slot >= MAX_SLOTS {
+            return Err(Error::InvalidSlot(slot));
+        }
+        let layout = self
+            .state::<Metadata>()
+            .0
+            .lock()
+            .unwrap()
+            .slots
+            .get(&slot)
+            .cloned()
+            .ok_or(Error::SlotEmpty(slot))?;
+        let labels: Vec<String> = layout.keys().cloned().collect();
+        self.state::<WindowStateCache>()
+            .0
+            .lock()
+            .unwrap()
+            .extend(layout);
+
+        for label in &labels {
+            if let Some(window) = self.get_window(label) {
+                window.restore_state(window.tracked_flags())?;
+            }
+        }
+
+        let policy = self.state::<PluginConfig>().unknown_in_layout;
+        if policy != UnknownPolicy::LeaveAsIs {
+            let tracked: Vec<String> = self
+                .state::<WindowStateCache>()
+                .0
+                .lock()
+                .unwrap()
+                .keys()
+                .filter(|key| !labels.contains(key))
+                .cloned()
+                .collect();
+            for window in tracked.iter().filter_map(|label| self.get_window(label)) {
+                match policy {
+                    UnknownPolicy::LeaveAsIs => {}
+                    UnknownPolicy::Hide => window.hide()?,
+                    UnknownPolicy::Center => window.center()?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn for_each_window_state(&self, mut f: impl FnMut(&str, &WindowState)) {
+        let cache = self.state::<WindowStateCache>();
+        for (label, state) in cache.0.lock().unwrap().iter() {
+            f(label, state);
+        }
+    }
+
+    fn was_window_visible(&self, label: &str) -> Option<bool> {
+        let cache = self.state::<WindowStateCache>();
+        let state = cache.0.lock().unwrap();
+        state.get(label).map(|s| s.visible)
+    }
+
+    fn previously_open_labels(&self) -> Vec<String> {
+        self.state::<PreviouslyOpen>().0.clone()
+    }
+
+    fn capture_layout(&self) -> Result<usize> {
+        update_from_windows(self, None).map_err(Into::into)
+    }
+
+    fn commit_layout(&self) -> Result<()> {
+        let cache = self.state::<WindowStateCache>();
+        let state = cache.0.lock().unwrap();
+        write_state(self, &state)
+    }
+
+    fn last_exit_was_clean(&self) -> Option<bool> {
+        self.state::<LastExitClean>().0
+    }
+
+    fn clear_all_window_states(&self) -> Result<()> {
+        let cache = self.state::<WindowStateCache>();
+        let mut state = cache.0.lock().unwrap();
+        state.clear();
+        write_state(self, &state)
+    }
+
+    fn delete_state_file(&self) -> Result<()> {
+        let cache = self.state::<WindowStateCache>();
+        let mut state = cache.0.lock().unwrap();
+        state.clear();
+        *self.state::<Metadata>().0.lock().unwrap() = Default::default();
+        match self
+            .state::<Store>()
+            .0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|store| store.path())
+        {
+            Some(path) => remove_state_files(path),
+            None => Ok(()),
+        }
+    }
+
+    fn set_state_path(&self, path: PathBuf, flush: bool) -> Result<()> {
+        // the windows are read before locking, see `update_from_windows`
+        if flush {
+            update_from_windows(self, None)?;
+        }
+
+        // holding the cache lock for the whole switch keeps concurrent saves out
+        let cache = self.state::<WindowStateCache>();
+        let mut state = cache.0.lock().unwrap();
+
+        if flush {
+            write_state(self, &state)?;
+        }
+
+        let store = FileStore::new(path);
+        let mut loaded = read_state(&store)?;
+        sanitize_state(self, &mut loaded);
+        replace_state(self, &mut state, loaded);
+        *self.state::<Store>().0.lock().unwrap() = Some(Box::new(store));
+
+        Ok(())
+    }
+
+    fn compact_state(&self, keep: impl Fn(&str) -> bool) -> Result<usize> {
+        let cache = self.state::<WindowStateCache>();
+        let mut state = cache.0.lock().unwrap();
+        let len = state.len();
+        state.retain(|label, _| keep(label));
+        write_state(self, &state)?;
+        Ok(len - state.len())
+    }
+
+    fn compact_to_current_windows(&self) -> Result<usize> {
+        let windows = self.windows();
+        self.compact_state(|key| windows.contains_key(key_label(key)))
+    }
+
+    fn subscribe_changes(&self) -> Receiver<WindowStateChange> {
+        let (tx, rx) = channel();
+        self.state::<Subscribers>().0.lock().unwrap().push(tx);
+        rx
+    }
+
+    fn serialize_state(&self) -> Result<Vec<u8>> {
+        update_from_windows(self, None)?;
+        let cache = self.state::<WindowStateCache>();
+        let state = cache.0.lock().unwrap();
+        encode_state(self, &state)
+    }
+
+    fn deserialize_state(&self, bytes: &[u8]) -> Result<()> {
+        let mut loaded = deserialize_state(bytes)?;
+        sanitize_state(self, &mut loaded);
+        let cache = self.state::<WindowStateCache>();
+        replace_state(self, &mut cache.0.lock().unwrap(), loaded);
+        Ok(())
+    }
+
+    fn restore_before_show(&self, label: &str) -> tauri::Result<()> {
+        if let Some(window) = self.get_window(label) {
+            window.restore_state_hidden()?;
+            let restored = self.state::<RestoredBeforeShow>();
+            restored.0.lock().unwrap().insert(label.into());
+        }
+        Ok(())
+    }
+
+    fn create_restored_window(&self, config: WindowConfig) -> tauri::Result<Window<R>> {
+        let visible = config.visible;
+        let window = tauri::WindowBuilder::from_config(self, config)
+            .visible(false)
+            .build()?;
+        // the plugin's own restore would run again once the webview is ready
+        let restored = self.state::<RestoredBeforeShow>();
+        restored.0.lock().unwrap().insert(window.label().into());
+        if visible {
+            window.show_restored()?;
+        } else {
+            window.restore_state_hidden()?;
+        }
+        Ok(window)
+    }
+
+    fn restore_all_windows(&self, flags: StateFlags) -> tauri::Result<()> {
+        let mut windows: Vec<Window<R>> = {
+            let cache = self.state::<WindowStateCache>();
+            let cache = cache.0.lock().unwrap();
+            self.windows()
+                .into_values()
+                .filter(|w| cache.contains_key(w.label()))
+                .collect()
+        };
+        windows.sort_by(|a, b| a.label().cmp(b.label()));
+
+        // window operations wait on the event loop, so running them elsewhere
+        // while the event loop waits here for them would deadlock
+        let on_event_loop = std::thread::current().id() == self.state::<EventLoopThread>().0;
+        if self.state::<PluginConfig>().parallel_restore && !on_event_loop {
+            // the results come back over a channel rather than by blocking on the tasks,
+            // which panics when called from a task of the async runtime
+            let (tx, rx) = channel();
+            for window in windows.iter().cloned() {
+                let tx = tx.clone();
+                tauri::async_runtime::spawn_blocking(move || {
+                    let _ = tx.send(window.restore_state(flags));
+                });
+            }
+            drop(tx);
+            let mut result = Ok(());
+            for restored in rx {
+                result = result.and(restored);
+            }
+            result?;
+        } else {
+            for window in &windows {
+                window.restore_state(flags)?;
+            }
+        }
+
+        let tiling = self
+            .state::<PluginConfig>()
+            .default_tiling
+            .filter(|_| flags.intersects(StateFlags::SIZE | StateFlags::POSITION));
+        if let Some(tiling) = tiling {
+            self.tile_first_seen(&windows, tiling)?;
+        }
+
+        // focusing is best-effort, the OS may refuse to move focus between windows
+        let (focused_label, focused_per_monitor) = {
+            let metadata = self.state::<Metadata>();
+            let metadata = metadata.0.lock().unwrap();
+            (
+                metadata.focused_label.clone(),
+                metadata.focused_per_monitor.clone(),
+            )
+        };
+        // the window focused last overall goes last, so it keeps the keyboard focus
+        let per_monitor = focused_per_monitor
+            .into_values()
+            .filter(|label| Some(label) != focused_label.as_ref());
+        for label in per_monitor.chain(focused_label.clone()) {
+            if let Some(window) = self.get_window(&label) {
+                let _ = window.set_focus();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+trait AppHandleExtInternal<R: Runtime> {
+    /// Arranges the windows that had no stored state on startup into tiles.
+    fn tile_first_seen(&self, windows: &[Window<R>], tiling: TilingMode) -> tauri::Result<()>;
+}
+
+impl<R: Runtime> AppHandleExtInternal<R> for tauri::AppHandle<R> {
+    fn tile_first_seen(&self, windows: &[Window<R>], tiling: TilingMode) -> tauri::Result<()> {
+        let first_seen: Vec<&Window<R>> = {
+            let loaded = self.state::<LoadedLabels>();
+            let loaded = loaded.0.lock().unwrap();
+            windows
+                .iter()
+                .filter(|w| !loaded.contains(w.label()))
+                .collect()
+        };
+
+        let monitor = match first_seen.first() {
+            Some(window) => match window.current_monitor()? {
+                Some(m) => Some(m),
+                None => window.primary_monitor()?,
+            },
+            None => None,
+        };
+        if let Some(monitor) = monitor {
+            let cells = tiling.cells(*monitor.position(), *monitor.size(), first_seen.len());
+            for (window, (position, size)) in first_seen.into_iter().zip(cells) {
+                // the cells include the window decorations
+                let outer = window.outer_size()?;
+                let inner = window.inner_size()?;
+                window.set_size(PhysicalSize {
+                    width: size
+                        .width
+                        .saturating_sub(outer.width.saturating_sub(inner.width)),
+                    height: size
+                        .height
+                        .saturating_sub(outer.height.saturating_sub(inner.height)),
+                })?;
+                window.set_position(position)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub trait WindowExt {
+    /// Restores this window state from disk
+    ///
+    /// Windows that were visible are shown and focused, unless turned off with [`AppHandleExt::set_auto_show`].
+    fn restore_state(&self, flags: StateFlags) -> tauri::Result<()>;
+    /// Like [`WindowExt::restore_state`], but returns where the applied state came from,
+    /// whether the window was shown, and how the applied geometry deviates from the stored one.
+    fn restore_state_detailed(&self, flags: StateFlags) -> tauri::Result<RestoreOutcome>;
+    /// Restores this window state from disk using the flags set with [`Builder::with_state_flags`],
+    /// but never shows or focuses the window, even if it was visible when its state was saved.
+    ///
+    /// Unlike leaving [`StateFlags::VISIBLE`] out of the flags, the visibility of windows
+    /// seen for the first time is still recorded, so the window can be revealed later.
+    fn restore_state_hidden(&self) -> tauri::Result<()>;
+    /// Restores this window state from disk using the flags set with [`Builder::with_state_flags`],
+    /// but places the window on the monitor named `monitor_name` instead of its stored position.
+    ///
+    /// The window keeps its offset from the monitor it was saved on if it fits on the named monitor,
+    /// otherwise it gets centered on it. Falls back to the regular restore if there is no such monitor,
+    /// no stored state for the window or its position isn't tracked.
+    /// The window is only shown if `auto_show` is `true` and it was visible when its state was saved.
+    fn restore_state_on_monitor(&self, monitor_name: &str, auto_show: bool) -> tauri::Result<()>;
+    /// Stores the current state of this window using the flags set with [`Builder::with_state_flags`],
+    /// without saving it to disk. Call [`AppHandleExt::save_window_state`] to persist it later.
+    ///
+    /// Like the automatic tracking, capturing a maximized window only records that it is
+    /// maximized and keeps its stored normal size and position, so it un-maximizes to its last
+    /// normal geometry. Capturing a fullscreen window keeps the stored [`FullscreenMode`].
+    fn capture_state(&self) -> tauri::Result<()>;
+    /// Restores this window state using the flags set with [`Builder::with_state_flags`],
+    /// then shows and focuses the window regardless of the stored visibility.
+    ///
+    /// Meant for tray apps reshowing a window that was hidden rather than closed,
+    /// for example from a tray menu handler.
+    fn show_restored(&self) -> tauri::Result<()>;
+    /// Restores the state stored for `route` of this window with [`WindowExt::capture_state_for_route`],
+    /// using the flags set with [`Builder::with_state_flags`].
+    ///
+    /// Lets an app showing different routes in one window give each route its own geometry,
+    /// for example a compact mini player next to the full view, by calling this on navigation.
+    /// The window is only shown if `auto_show` is `true` and it was visible when its state was saved.
+    ///
+    /// Route states are only updated by [`WindowExt::capture_state_for_route`], not by the
+    /// automatic tracking, so don't mix both keying strategies for the same window: turn off
+    /// its automatic tracking with [`WindowExt::set_state_persistence`] or [`Builder::with_denylist`].
+    fn restore_state_for_route(&self, route: &str, auto_show: bool) -> tauri::Result<()>;
+    /// Stores the current state of this window for `route` without saving it to disk,
+    /// see [`WindowExt::restore_state_for_route`].
+    fn capture_state_for_route(&self, route: &str) -> tauri::Result<()>;
+    /// Marks the start of programmatic geometry changes for this window.
+    ///
+    /// Until the matching [`WindowExt::end_programmatic`] call, the plugin ignores
+    /// moves and resizes of this window so transient, app-driven geometry
+    /// (splash transitions, responsive relayouts) isn't remembered as the user's preference.
+    /// The runtime can't tell the two apart, so the app has to bracket its own calls.
+    /// Brackets can be nested.
+    fn begin_programmatic(&self);
+    /// Marks the end of programmatic geometry changes started with [`WindowExt::begin_programmatic`].
+    fn end_programmatic(&self);
+    /// Sets whether the state of this window should be tracked and saved,
+    /// overriding [`Builder::with_denylist`] for this window.
+    ///
+    /// Call this right after creating the window: the plugin decides whether to restore
+    /// and track a window once its webview is ready. Turning persistence off later stops
+    /// updates to the stored state, but keeps the state that was already stored.
+    fn set_state_persistence(&self, enabled: bool);
+    /// Puts this window into `category`, so the plugin uses the flags set for it
+    /// with [`Builder::with_window_type_flags`] instead of [`Builder::with_state_flags`].
+    ///
+    /// An override set with [`WindowExt::set_state_persistence`] takes precedence over the
+    /// category, which takes precedence over the global flags. Call this right after creating
+    /// the window, before its webview is ready. Windows in a category without flags use the
+    /// global ones.
+    fn set_window_category(&self, category: &str);
+    /// Pauses or resumes saving the state of this window, for example while it is used as
+    /// a transient preview, at any point of its lifetime.
+    ///
+    /// While paused, moves and resizes are ignored and saves keep the state stored before.
+    /// The stored state isn't removed, and resuming tracks changes again from then on.
+    /// Unlike [`WindowExt::set_state_persistence`], this doesn't change whether the window is
+    /// restored.
+    fn set_save_enabled(&self, enabled: bool);
+    /// Marks this window as created with an explicit position and size, so its initial
+    /// restore only restores the other flags, like maximized and fullscreen.
+    ///
+    /// Call this right after creating the window, before its webview is ready. By default,
+    /// or when called later, the stored size and position override the ones the app set.
+    /// The window is tracked as usual, and explicit calls to [`WindowExt::restore_state`]
+    /// still restore its size and position.
+    fn mark_explicitly_placed(&self);
+    /// Overrides the idle duration set with [`Builder::with_idle_flush`] for this window,
+    /// so its moves and resizes are saved once it was left untouched for `interval`.
+    ///
+    /// Works without a builder-level idle flush as well. `None` falls back to the builder setting.
+    fn set_autosave_interval(&self, interval: Option<Duration>);
+    /// Stores `value` with the state of this window, for example split ratios or the selected tab,
+    /// replacing previously stored data. `None` removes it.
+    ///
+    /// The plugin persists the data with the window state but never interprets it.
+    fn set_user_data(&self, value: Option<serde_json::Value>);
+    /// Returns the data stored with [`WindowExt::set_user_data`] for this window.
+    fn get_user_data(&self) -> Option<serde_json::Value>;
+    /// Forgets the stored state of this window and applies `default` right away,
+    /// for example from a "reset this window" button.
+    ///
+    /// The plugin doesn't know the app's intended defaults, so the caller supplies them.
+    /// Only the parts of `default` selected with [`Builder::with_state_flags`] are applied,
+    /// and the window keeps being tracked from its reset state.
+    fn reset_to_default(&self, default: WindowState) -> tauri::Result<()>;
+    /// Returns what restoring this window with `flags` would do, without changing the window,
+    /// including the [`RestorePlan::adjustments`] explaining where it would end up and why.
+    ///
+    /// Returns `None` if there is no stored geometry to restore, or if the window always opens
+    /// at its default, see [`Builder::with_always_default`]. The interceptor set with
+    /// [`Builder::with_restore_interceptor`] isn't called.
+    fn preview_restore(&self, flags: StateFlags) -> tauri::Result<Option<RestorePlan>>;
+    /// Restores this window from the first available source, using the flags set with
+    /// [`Builder::with_state_flags`]: its stored state, otherwise the first of `fallbacks`
+    /// with a nonzero size, otherwise the geometry it was created with, centered.
+    ///
+    /// Meant for apps shipping default layouts while honoring the user's changes.
+    /// A fallback that gets applied becomes the stored state of the window, keeping its
+    /// [`WindowState::user_data`], so later saves track the user's changes from there.
+    /// The window is only shown if `auto_show` is `true` and the source was visible.
+    fn restore_state_with_fallback(
+        &self,
+        fallbacks: &[WindowState],
+        auto_show: bool,
+    ) -> tauri::Result<()>;
+    /// Places this window relative to the window `parent_label` from now on, `offset` physical
+    /// pixels from the outer position of the parent, for tool windows docked to a main window.
+    ///
+    /// Restoring puts the window at the current position of the parent plus the offset,
+    /// instead of at its stored position, unless it's maximized or fullscreen. When the parent
+    /// isn't open, the stored position is restored as usual. Saving the window while the parent
+    /// is open updates the offset, so moving the window keeps it docked where the user put it.
+    /// This doesn't move the window right away.
+    fn set_relative_to(&self, parent_label: &str, offset: PhysicalPosition<i32>);
+    /// Stops placing this window relative to the window set with [`WindowExt::set_relative_to`].
+    fn clear_relative_to(&self);
+    /// Stores the zoom factor the user chose for this window's webview, if the flags
+    /// of this window include [`StateFlags::ZOOM`]. Otherwise this does nothing.
+    ///
+    /// Tauri can't read or change the zoom of a webview, so the app reports it here
+    /// whenever it changes it, and restoring doesn't apply it. Read it back with
+    /// [`WindowExt::get_zoom_persisted`] and apply it from the frontend instead.
+    fn set_zoom_persisted(&self, factor: f64);
+    /// Returns the zoom factor stored with [`WindowExt::set_zoom_persisted`] for this window.
+    fn get_zoom_persisted(&self) -> Option<f64>;
+    /// Takes a snapshot of the size, position, maximized and fullscreen modes and decorations
+    /// of this window, for example before entering a kiosk mode, to return to with [`WindowExt::restore_stashed`].
+    ///
+    /// Stashes only live in memory, they're neither saved nor part of the stored state,
+    /// and they're dropped when the app exits. A window can have several stashes at once.
+    fn stash_state(&self) -> tauri::Result<StateToken>;
+    /// Returns this window to the geometry stashed with [`WindowExt::stash_state`],
+    /// leaving fullscreen and maximized modes the window wasn't in when it was stashed.
+    ///
+    /// The token is consumed and its stash dropped. Tokens stashed for other windows are ignored.
+    fn restore_stashed(&self, token: StateToken) -> tauri::Result<()>;
+    /// Maximizes this window or makes it fullscreen if it was when its state was saved,
+    /// leaving its size and position to the app.
+    ///
+    /// Like [`WindowExt::restore_state`] with only [`StateFlags::MAXIMIZED`] and
+    /// [`StateFlags::FULLSCREEN`], of the ones set with [`Builder::with_state_flags`], but without
+    /// moving a maximized window onto the monitor it was maximized on. Windows aren't minimized,
+    /// see [`WindowState::minimized`]. Does nothing if the window has no stored state.
+    fn restore_window_mode(&self) -> tauri::Result<()>;
+    /// Returns the monitors restoring this window considers, without the ones set with
+    /// [`Builder::with_excluded_monitors`], in the form [`WindowState::monitor`] stores them.
+    ///
+    /// Tauri doesn't expose the work area of a monitor, so the full monitor area is returned.
+    fn monitors_snapshot(&self) -> tauri::Result<Vec<MonitorInfo>>;
+}
+
+impl<R: Runtime> WindowExt for Window<R> {
+    fn restore_state(&self, flags: StateFlags) -> tauri::Result<()> {
+        self.restore_state_detailed(flags).map(|_| ())
+    }
+
+    fn restore_state_detailed(&self, flags: StateFlags) -> tauri::Result<RestoreOutcome> {
+        let auto_show = self.app_handle().auto_show();
+        self.restore_state_keyed(self.label(), flags, auto_show)
+    }
+
+    fn restore_state_hidden(&self) -> tauri::Result<()> {
+        let flags = self.tracked_flags();
+        self.restore_state_with(flags, false)
+    }
+
+    fn restore_state_on_monitor(&self, monitor_name: &str, auto_show: bool) -> tauri::Result<()> {
+        let flags = self.tracked_flags();
+        // windows whose position isn't tracked or that have no stored state aren't moved
+        let stored = match flags.contains(StateFlags::POSITION) {
+            true => self
+                .state::<WindowStateCache>()
+                .0
+                .lock()
+                .unwrap()
+                .get(self.label())
+                .cloned(),
+            false => None,
+        };
+        let monitor = self
+            .available_monitors()?
+            .into_iter()
+            .find(|m| m.name().map_or(false, |name| name == monitor_name));
+        let (state, monitor) = match (stored, monitor) {
+            (Some(state), Some(monitor)) => (state, monitor),
+            _ => return self.restore_state_with(flags, auto_show),
+        };
+
+        let size =
+            LogicalSize::new(state.width, state.height).to_physical::<i32>(monitor.scale_factor());
+        let origin = *monitor.position();
+        let bounds = monitor.size().cast::<i32>();
+        let centered = PhysicalPosition::new(
+            origin.x + (bounds.width - size.width) / 2,
+            origin.y + (bounds.height - size.height) / 2,
+        );
+        // keep the offset from the saved monitor if the window fits there on the new monitor
+        let (x, y) = match state.maximized {
+            true => (state.prev_x, state.prev_y),
+            false => (state.x, state.y),
+        };
+        let position = state
+            .monitor
+            .as_ref()
+            .and_then(|saved| {
+                let (dx, dy) = (x - saved.x, y - saved.y);
+                let fits = dx >= 0
+                    && dy >= 0
+                    && dx + size.width <= bounds.width
+                    && dy + size.height <= bounds.height;
+                fits.then(|| PhysicalPosition::new(origin.x + dx, origin.y + dy))
+            })
+            .unwrap_or(centered);
+
+        // move first, so maximized and fullscreen windows fill the named monitor
+        self.set_position(position)?;
+        self.restore_state_with(flags - StateFlags::POSITION, auto_show)
+    }
+
+    fn capture_state(&self) -> tauri::Result<()> {
+        self.capture_state_keyed(self.label())
+    }
+
+    fn show_restored(&self) -> tauri::Result<()> {
+        let flags = self.tracked_flags();
+        self.restore_state_with(flags, false)?;
+        self.show()?;
+        self.set_focus()?;
+        if REAPPLY_GEOMETRY_AFTER_SHOW && !self.is_maximized()? && !self.is_fullscreen()? {
+            // the window manager may place windows anew when they're shown
+            self.restore_state_with(flags & (StateFlags::SIZE | StateFlags::POSITION), false)?;
+        }
+        Ok(())
+    }
+
+    fn restore_state_for_route(&self, route: &str, auto_show: bool) -> tauri::Result<()> {
+        let flags = self.tracked_flags();
+        self.restore_state_keyed(&route_key(self.label(), route), flags, auto_show)
+            .map(|_| ())
+    }
+
+    fn capture_state_for_route(&self, route: &str) -> tauri::Result<()> {
+        self.capture_state_keyed(&route_key(self.label(), route))
+    }
+
+    fn begin_programmatic(&self) {
+        let programmatic = self.state::<ProgrammaticWindows>();
+        let mut programmatic = programmatic.0.lock().unwrap();
+        *programmatic.entry(self.label().into()).or_default() += 1;
+    }
+
+    fn end_programmatic(&self) {
+        let programmatic = self.state::<ProgrammaticWindows>();
+        let mut programmatic = programmatic.0.lock().unwrap();
+        if let Some(depth) = programmatic.get_mut(self.label()) {
+            *depth -= 1;
+            if *depth == 0 {
+                programmatic.remove(self.label());
+            }
+        }
+    }
+
+    fn set_user_data(&self, value: Option<serde_json::Value>) {
+        let cache = self.state::<WindowStateCache>();
+        let mut c = cache.0.lock().unwrap();
+        c.entry(self.label().into()).or_default().user_data = value;
+    }
+
+    fn get_user_data(&self) -> Option<serde_json::Value> {
+        let cache = self.state::<WindowStateCache>();
+        let c = cache.0.lock().unwrap();
+        c.get(self.label())
+            .and_then(|state| state.user_data.clone())
+    }
+
+    fn reset_to_default(&self, default: WindowState) -> tauri::Result<()> {
+        let flags = self.tracked_flags();
+        {
+            let cache = self.state::<WindowStateCache>();
+            let mut c = cache.0.lock().unwrap();
+            c.insert(self.label().into(), WindowState::default());
+        }
+
+        if flags.contains(StateFlags::FULLSCREEN) {
+            self.set_fullscreen(default.fullscreen.is_fullscreen())?;
+        }
+        if flags.contains(StateFlags::MAXIMIZED) && !default.maximized {
+            self.unmaximize()?;
+        }
+        if flags.contains(StateFlags::DECORATIONS) {
+            self.set_decorations(default.decorated)?;
+        }
+        let size = flags
+            .contains(StateFlags::SIZE)
+            .then(|| LogicalSize::new(default.width, default.height));
+        let position = flags
+            .contains(StateFlags::POSITION)
+            .then(|| PhysicalPosition::new(default.x, default.y));
+        self.apply_geometry(size, position)?;
+        if flags.contains(StateFlags::MAXIMIZED) && default.maximized {
+            self.maximize()?;
+        }
+        Ok(())
+    }
+
+    fn mark_explicitly_placed(&self) {
+        let explicitly_placed = self.state::<ExplicitlyPlaced>();
+        explicitly_placed
+            .0
+            .lock()
+            .unwrap()
+            .insert(self.label().into());
+    }
+
+    fn set_autosave_interval(&self, interval: Option<Duration>) {
+        let worker = self.state::<SaveWorker>();
+        let mut overrides = worker.overrides.lock().unwrap();
+        match interval {
+            Some(interval) => overrides.insert(self.label().into(), interval),
+            None => overrides.remove(self.label()),
+        };
+    }
+
+    fn set_state_persistence(&self, enabled: bool) {
+        let preferences = self.state::<PersistencePreferences>();
+        preferences
+            .0
+            .lock()
+            .unwrap()
+            .insert(self.label().into(), enabled);
+    }
+
+    fn set_save_enabled(&self, enabled: bool) {
+        self.state::<SavePaused>()
+            .flag(self.label())
+            .store(!enabled, Ordering::Relaxed);
+    }
+
+    fn set_window_category(&self, category: &str) {
+        let categories = self.state::<WindowCategories>();
+        categories
+            .tags
+            .lock()
+            .unwrap()
+            .insert(self.label().into(), category.into());
+    }
+
+    fn restore_state_with_fallback(
+        &self,
+        fallbacks: &[WindowState],
+        auto_show: bool,
+    ) -> tauri::Result<()> {
+        let flags = self.tracked_flags();
+        let has_stored_state = {
+            let cache = self.state::<WindowStateCache>();
+            let mut c = cache.0.lock().unwrap();
+            let stored = c.get(self.label());
+            let has_stored_state = stored.map_or(false, |state| !state.geometry_unset());
+            let fallback = fallbacks
+                .iter()
+                .find(|fallback| fallback.width > 0. && fallback.height > 0.);
+            match fallback {
+                Some(fallback) if !has_stored_state => {
+                    let user_data = stored.and_then(|state| state.user_data.clone());
+                    c.insert(
+                        self.label().into(),
+                        WindowState {
+                            user_data,
+                            ..fallback.clone()
+                        },
+                    );
+                    true
+                }
+                _ => has_stored_state,
+            }
+        };
+
+        if has_stored_state {
+            return self.restore_state_with(flags, auto_show);
+        }
+        if flags.contains(StateFlags::POSITION) {
+            self.center()?;
+        }
+        if auto_show && flags.contains(StateFlags::VISIBLE) {
+            self.show()?;
+            self.set_focus()?;
+        }
+        Ok(())
+    }
+
+    fn preview_restore(&self, flags: StateFlags) -> tauri::Result<Option<RestorePlan>> {
+        if self.state::<AlwaysDefault>().0.contains_key(self.label()) {
+            return Ok(None);
+        }
+        let origin = self.primary_origin()?;
+        let stored = {
+            let cache = self.state::<WindowStateCache>();
+            let mut c = cache.0.lock().unwrap();
+            if let Some(origin) = origin {
+                let metadata = self.state::<Metadata>();
+                rebase_positions(&mut c, &mut metadata.0.lock().unwrap(), origin);
+            }
+            c.get(self.label()).cloned()
+        };
+        match stored {
+            Some(mut state) if !state.geometry_unset() => {
+                self.plan_restore(&mut state, flags).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn set_relative_to(&self, parent_label: &str, offset: PhysicalPosition<i32>) {
+        let cache = self.state::<WindowStateCache>();
+        let mut c = cache.0.lock().unwrap();
+        c.entry(self.label().into()).or_default().relative_to = Some(RelativePlacement {
+            parent: parent_label.into(),
+            offset,
+        });
+    }
+
+    fn clear_relative_to(&self) {
+        let cache = self.state::<WindowStateCache>();
+        let mut c = cache.0.lock().unwrap();
+        if let Some(state) = c.get_mut(self.label()) {
+            state.relative_to = None;
+        }
+    }
+
+    fn set_zoom_persisted(&self, factor: f64) {
+        if !self.tracked_flags().contains(StateFlags::ZOOM) {
+            return;
+        }
+        let cache = self.state::<WindowStateCache>();
+        let mut c = cache.0.lock().unwrap();
+        c.entry(self.label().into()).or_default().zoom = Some(factor);
+    }
+
+    fn get_zoom_persisted(&self) -> Option<f64> {
+        let cache = self.state::<WindowStateCache>();
+        let c = cache.0.lock().unwrap();
+        c.get(self.label()).and_then(|state| state.zoom)
+    }
+
+    fn monitors_snapshot(&self) -> tauri::Result<Vec<MonitorInfo>> {
+        WindowOps::monitors(self)
+    }
+
+    fn restore_window_mode(&self) -> tauri::Result<()> {
+        let flags = self.tracked_flags();
+        let stored = self
+            .state::<WindowStateCache>()
+            .0
+            .lock()
+            .unwrap()
+            .get(self.label())
+            .cloned();
+        let state = match stored {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+        if flags.contains(StateFlags::MAXIMIZED) && state.maximized {
+            self.maximize()?;
+        }
+        if flags.contains(StateFlags::FULLSCREEN) && state.fullscreen.is_fullscreen() {
+            self.set_fullscreen(true)?;
+        }
+        Ok(())
+    }
+
+    fn stash_state(&self) -> tauri::Result<StateToken> {
+        // start from the stored state, which keeps the normal geometry of maximized windows
+        let mut state = self
+            .state::<WindowStateCache>()
+            .0
+            .lock()
+            .unwrap()
+            .get(self.label())
+            .cloned()
+            .unwrap_or_default();
+        self.update_state(
+            &mut state,
+            StateFlags::SIZE
+                | StateFlags::POSITION
+                | StateFlags::MAXIMIZED
+                | StateFlags::FULLSCREEN
+                | StateFlags::DECORATIONS,
+        )?;
+        let stashes = self.state::<Stashes>();
+        let id = stashes.next.fetch_add(1, Ordering::Relaxed);
+        stashes
+            .states
+            .lock()
+            .unwrap()
+            .insert(id, (self.label().into(), state));
+        Ok(StateToken(id))
+    }
+
+    fn restore_stashed(&self, token: StateToken) -> tauri::Result<()> {
+        let stashed = {
+            let mut states = self.state::<Stashes>().states.lock().unwrap();
+            match states.get(&token.0) {
+                Some((label, _)) if label == self.label() => states.remove(&token.0),
+                _ => None,
+            }
+        };
+        let state = match stashed {
+            Some((_, state)) => state,
+            None => return Ok(()),
+        };
+
+        if !state.fullscreen.is_fullscreen() && self.is_fullscreen()? {
+            self.set_fullscreen(false)?;
+        }
+        if !state.maximized && self.is_maximized()? {
+            self.unmaximize()?;
+        }
+        self.set_decorations(state.decorated)?;
+        self.apply_geometry(
+            Some(LogicalSize::new(state.width, state.height)),
+            Some(PhysicalPosition::new(state.x, state.y)),
+        )?;
+        if state.maximized {
+            self.maximize()?;
+        }
+        if state.fullscreen.is_fullscreen() {
+            self.set_fullscreen(true)?;
+        }
+        Ok(())
+    }
+}
+
+trait WindowExtInternal {
+    /// Restores this window state from disk, only showing it if `show` is `true`.
+    fn restore_state_with(&self, flags: StateFlags, show: bool) -> tauri::Result<()>;
+    /// Restores the state stored under `key` onto this window, only showing it if `show` is `true`.
+    fn restore_state_keyed(
+        &self,
+        key: &str,
+        flags: StateFlags,
+        show: bool,
+    ) -> tauri::Result<RestoreOutcome>;
+    /// Applies the state stored under `key` without rolling back on errors.
+    fn apply_stored_state(
+        &self,
+        key: &str,
+        flags: StateFlags,
+        show: bool,
+    ) -> tauri::Result<RestoreOutcome>;
+    /// Stores the current state of this window under `key`.
+    fn capture_state_keyed(&self, key: &str) -> tauri::Result<()>;
+    /// Reads the properties `flags` track, along with the position of the open window `parent`,
+    /// see [`WindowProperties`]. Must be called without holding a lock of the plugin,
+    /// see [`update_from_windows`].
+    fn read_properties(
+        &self,
+        flags: StateFlags,
+        parent: Option<&str>,
+    ) -> tauri::Result<WindowProperties>;
+    /// Updates `state` from this window, must be called without holding a lock of the plugin,
+    /// see [`update_from_windows`].
+    fn update_state(&self, state: &mut WindowState, flags: StateFlags) -> tauri::Result<()>;
+    /// The origin of the primary monitor if [`Builder::with_primary_relative_coords`] is set.
+    fn primary_origin(&self) -> tauri::Result<Option<PhysicalPosition<i32>>>;
+    /// Plans restoring `state` with `flags`, remapping it onto the connected monitors first.
+    fn plan_restore(
+        &self,
+        state: &mut WindowState,
+        flags: StateFlags,
+    ) -> tauri::Result<RestorePlan>;
+    fn apply_geometry(
+        &self,
+        size: Option<LogicalSize<f64>>,
+        position: Option<PhysicalPosition<i32>>,
+    ) -> tauri::Result<()>;
+    /// Centers this window on the monitor the cursor is on,
+    /// if [`Builder::with_restore_to_cursor_monitor`] is set.
+    fn move_to_cursor_monitor(&self) -> tauri::Result<()>;
+    /// The flags of this window's category, falling back to [`Builder::with_state_flags`].
+    fn tracked_flags(&self) -> StateFlags;
+    /// The position of `state` relative to its open parent window,
+    /// see [`WindowExt::set_relative_to`].
+    fn relative_position(
+        &self,
+        state: &WindowState,
+    ) -> tauri::Result<Option<PhysicalPosition<i32>>>;
+    /// Whether this window covers enough of its monitor to be saved as maximized,
+    /// see [`Builder::with_maximize_detection_threshold`].
+    fn fills_monitor(&self) -> tauri::Result<bool>;
+}
+
+impl<R: Runtime> WindowExtInternal for Window<R> {
+    fn restore_state_with(&self, flags: StateFlags, show: bool) -> tauri::Result<()> {
+        self.restore_state_keyed(self.label(), flags, show)
+            .map(|_| ())
+    }
+
+    fn restore_state_keyed(
+        &self,
+        key: &str,
+        flags: StateFlags,
+        show: bool,
+    ) -> tauri::Result<RestoreOutcome> {
+        if !self.state::<PluginConfig>().atomic_restore {
+            return self.apply_stored_state(key, flags, show);
+        }
+
+        with_rollback(self, || self.apply_stored_state(key, flags, show))
+    }
+
+    fn apply_stored_state(
+        &self,
+        key: &str,
+        flags: StateFlags,
+        show: bool,
+    ) -> tauri::Result<RestoreOutcome> {
+        let skipped = RestoreOutcome {
+            source: RestoreSource::Skipped,
+            shown: false,
+            adjustments: Vec::new(),
+        };
+        if let Some(size) = self.state::<AlwaysDefault>().0.get(self.label()) {
+            if let Some(size) = size.filter(|_| flags.contains(StateFlags::SIZE)) {
+                self.set_size(size)?;
+            }
+            if flags.contains(StateFlags::POSITION) {
+                self.center()?;
+            }
+            let shown = show && flags.contains(StateFlags::VISIBLE);
+            if shown {
+                self.show()?;
+                self.set_focus()?;
+            }
+            return Ok(RestoreOutcome {
+                source: RestoreSource::Default,
+                shown,
+                adjustments: Vec::new(),
+            });
+        }
+
+        let cache = self.state::<WindowStateCache>();
+        let change_threshold = self.state::<PluginConfig>().restore_change_threshold;
+        let before = match change_threshold {
+            Some(_) => Some(Geometry::of(self)?),
+            None => None,
+        };
+        let origin = self.primary_origin()?;
+        // only hold the lock briefly, so windows restoring at the same time
+        // don't wait on each other's window operations
+        let stored = {
+            let mut c = cache.0.lock().unwrap();
+            if let Some(origin) = origin {
+                let metadata = self.state::<Metadata>();
+                rebase_positions(&mut c, &mut metadata.0.lock().unwrap(), origin);
+            }
+            c.get(key).cloned()
+        };
+
+        let mut should_show = true;
+        // the geometry applied to the window, to apply again once it's shown
+        let mut geometry = None;
+        let was_hidden = REAPPLY_GEOMETRY_AFTER_SHOW && show && !self.is_visible()?;
+
+        let mut adjustments = Vec::new();
+        let source = match stored {
+            Some(_) => RestoreSource::Stored,
+            None => RestoreSource::Captured,
+        };
+        let mut shown = false;
+        if let Some(mut state) = stored {
+            // avoid restoring the default zeroed state, the app may have stored data for it already
+            if state.geometry_unset() {
+                return Ok(skipped);
+            }
+
+            let mut plan = self.plan_restore(&mut state, flags)?;
+            for adjustment in &plan.adjustments {
+                if let Adjustment::OversizeReduced {
+                    monitor,
+                    requested,
+                    clamped,
+                } = adjustment
+                {
+                    emit_warning(
+                        self,
+                        Warning::OversizeRestore {
+                            label: self.label().into(),
+                            monitor: monitor.clone(),
+                            requested: *requested,
+                            clamped: *clamped,
+                        },
+                    );
+                }
+            }
+            if let Some(interceptor) = &self.state::<RestoreInterceptor<R>>().0 {
+                if !interceptor(self, &mut plan) {
+                    return Ok(skipped);
+                }
+            }
+            adjustments = plan.adjustments.clone();
+
+            let warn = |warning| emit_warning(self, warning);
+            let options = self.state::<PluginConfig>().restore_options();
+            geometry = apply_plan(self, self.label(), &state, &mut plan, flags, options, &warn)?;
+
+            should_show = state.visible;
+        } else {
+            if flags.contains(StateFlags::POSITION) {
+                self.move_to_cursor_monitor()?;
+            }
+
+            let mut metadata = WindowState::default();
+
+            if flags.contains(StateFlags::SIZE) {
+                let scale_factor = self
+                    .current_monitor()?
+                    .map(|m| m.scale_factor())
+                    .unwrap_or(1.);
+                let size = self.inner_size()?.to_logical(scale_factor);
+                metadata.width = size.width;
+                metadata.height = size.height;
+            }
+
+            if flags.contains(StateFlags::POSITION) {
+                let pos = self.outer_position()?;
+                metadata.x = pos.x;
+                metadata.y = pos.y;
+            }
+
+            if flags.contains(StateFlags::MAXIMIZED) {
+                metadata.maximized = self.is_maximized()?;
+            }
+
+            if flags.contains(StateFlags::VISIBLE) {
+                metadata.visible = self.is_visible()?;
+            }
+
+            if flags.contains(StateFlags::DECORATIONS) {
+                metadata.decorated = self.is_decorated()?;
+            }
+
+            if flags.contains(StateFlags::FULLSCREEN) {
+                metadata.fullscreen = self.is_fullscreen()?.into();
+            }
+
+            if flags.intersects(StateFlags::SIZE | StateFlags::POSITION) {
+                metadata.monitor = self.current_monitor()?.as_ref().map(Into::into);
+            }
+
+            cache
+                .0
+                .lock()
+                .unwrap()
+                .entry(key.into())
+                .or_insert(metadata);
+        }
+
+        if show && flags.contains(StateFlags::VISIBLE) && should_show {
+            show_and_reapply(self, geometry.filter(|_| was_hidden))?;
+            shown = true;
+        } else if show && flags.contains(StateFlags::VISIBLE) {
+            match self.state::<PluginConfig>().hidden_policy {
+                HiddenPolicy::KeepHidden => {}
+                HiddenPolicy::Close => {
+                    self.close()?;
+                    // the closed window can't be inspected any further
+                    return Ok(RestoreOutcome {
+                        source,
+                        shown,
+                        adjustments,
+                    });
+                }
+                HiddenPolicy::Minimize => {
+                    self.minimize()?;
+                    self.show()?;
+                    shown = true;
+                }
+            }
+        }
+
+        if geometry.is_some() && self.state::<PluginConfig>().detect_wm_override {
+            let requested = Geometry::of(self)?;
+            let window = self.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(WM_OVERRIDE_DELAY);
+                check_wm_override(&window, requested);
+            });
+        }
+
+        if let (Some(threshold), Some(before)) = (change_threshold, before) {
+            let after = Geometry::of(self)?;
+            // the restore succeeded regardless of whether anyone is notified of it
+            if after.delta(&before) > threshold {
+                let _ = self.emit_all(
+                    RESTORED_WITH_CHANGES_EVENT,
+                    RestoredWithChanges {
+                        label: self.label().into(),
+                        before: validation::Rect::new(before.position, before.size),
+                        after: validation::Rect::new(after.position, after.size),
+                        adjustments: adjustments.clone(),
+                    },
+                );
+            }
+        }
+
+        Ok(RestoreOutcome {
+            source,
+            shown,
+            adjustments,
+        })
+    }
+
+    fn read_properties(
+        &self,
+        flags: StateFlags,
+        parent: Option<&str>,
+    ) -> tauri::Result<WindowProperties> {
+        let maximized = match flags.intersects(StateFlags::MAXIMIZED | StateFlags::SIZE) {
+            true => self.is_maximized()? || self.fills_monitor()?,
+            false => false,
+        };
+        let minimized = self.is_minimized()?;
+        let normal = !maximized && !minimized;
+        let monitor = self.current_monitor()?;
+        let mut properties = WindowProperties {
+            maximized,
+            minimized,
+            ..Default::default()
+        };
+
+        if flags.contains(StateFlags::MAXIMIZED) && maximized {
+            properties.maximized_monitor = monitor.as_ref().and_then(|m| m.name().cloned());
+        }
+        if flags.intersects(StateFlags::FULLSCREEN | StateFlags::SNAP) {
+            properties.fullscreen = self.is_fullscreen()?;
+        }
+        if flags.contains(StateFlags::DECORATIONS) {
+            properties.decorated = self.is_decorated()?;
+        }
+        if flags.contains(StateFlags::VISIBLE) {
+            properties.visible = self.is_visible()?;
+        }
+        if flags.contains(StateFlags::SIZE) {
+            let scale_factor = monitor.as_ref().map(|m| m.scale_factor()).unwrap_or(1.);
+            properties.size = self.inner_size()?.to_logical(scale_factor);
+        }
+        if flags.contains(StateFlags::THEME) {
+            properties.theme = Some(self.theme()?);
+        }
+
+        if normal && flags.intersects(StateFlags::SIZE | StateFlags::POSITION | StateFlags::SNAP) {
+            properties.position = self.outer_position()?;
+            let window = validation::Rect::new(properties.position, self.outer_size()?);
+            properties.monitor = monitor.as_ref().map(Into::into);
+
+            if flags.intersects(StateFlags::SIZE | StateFlags::POSITION) {
+                let overlapped: Vec<Monitor> = self
+                    .available_monitors()?
+                    .into_iter()
+                    .filter(|m| validation::overlaps(window, validation::Rect::of_monitor(m)))
+                    .collect();
+                if overlapped.len() > 1 {
+                    properties.spanned_monitors = overlapped
+                        .iter()
+                        .filter_map(|m| m.name().cloned())
+                        .collect();
+                }
+            }
+            if flags.contains(StateFlags::POSITION) {
+                properties.parent_origin = match parent.and_then(|p| self.get_window(p)) {
+                    Some(parent) => Some(parent.outer_position()?),
+                    None => None,
+                };
+            }
+            if flags.contains(StateFlags::SNAP) && !properties.fullscreen {
+                properties.snap = monitor
+                    .as_ref()
+                    .and_then(|m| SnapPosition::detect(window, validation::Rect::of_monitor(m)));
+            }
+        }
+
+        Ok(properties)
+    }
+
+    fn update_state(&self, state: &mut WindowState, flags: StateFlags) -> tauri::Result<()> {
+        let parent = state.relative_to.as_ref().map(|r| r.parent.as_str());
+        let properties = self.read_properties(flags, parent)?;
+        let percentage_positions = self.state::<PluginConfig>().percentage_positions;
+        merge_properties(state, &properties, flags, percentage_positions);
+        Ok(())
+    }
+
+    fn plan_restore(
+        &self,
+        state: &mut WindowState,
+        flags: StateFlags,
+    ) -> tauri::Result<RestorePlan> {
+        let relative = self.relative_position(state)?;
+        let options = self.state::<PluginConfig>().restore_options();
+        plan_geometry(self, state, flags, options, relative)
+    }
+
+    fn apply_geometry(
+        &self,
+        size: Option<LogicalSize<f64>>,
+        position: Option<PhysicalPosition<i32>>,
+    ) -> tauri::Result<()> {
+        set_geometry(self, size, position)
+    }
+
+    fn capture_state_keyed(&self, key: &str) -> tauri::Result<()> {
+        let flags = self.tracked_flags();
+        let cache = self.state::<WindowStateCache>();
+        let parent = cache
+            .0
+            .lock()
+            .unwrap()
+            .get(key)
+            .and_then(|s| s.relative_to.as_ref())
+            .map(|r| r.parent.clone());
+        let properties = self.read_properties(flags, parent.as_deref())?;
+        let percentage_positions = self.state::<PluginConfig>().percentage_positions;
+        let mut c = cache.0.lock().unwrap();
+        let state = c.entry(key.into()).or_default();
+        merge_properties(state, &properties, flags, percentage_positions);
+        self.state::<Subscribers>().notify(key, state);
+        Ok(())
+    }
+
+    fn move_to_cursor_monitor(&self) -> tauri::Result<()> {
+        let cursor = match self.state::<CursorPosition>().0.as_ref().and_then(|f| f()) {
+            Some(cursor) => cursor,
+            None => return Ok(()),
+        };
+        let monitor = self.available_monitors()?.into_iter().find(|m| {
+            validation::contains_point(
+                validation::Rect::of_monitor(m),
+                cursor.x as i64,
+                cursor.y as i64,
+            )
+        });
+        if let Some(monitor) = monitor {
+            let area = validation::Rect::of_monitor(&monitor);
+            let size = self.outer_size()?;
+            let centered = validation::clamp_to_work_area(
+                validation::Rect {
+                    x: area.x + (area.width as i32 - size.width as i32) / 2,
+                    y: area.y + (area.height as i32 - size.height as i32) / 2,
+                    width: size.width,
+                    height: size.height,
+                },
+                area,
+            );
+            self.set_position(PhysicalPosition::new(centered.x, centered.y))?;
+        }
+        Ok(())
+    }
+
+    fn primary_origin(&self) -> tauri::Result<Option<PhysicalPosition<i32>>> {
+        if !self.state::<PluginConfig>().primary_relative_coords {
+            return Ok(None);
+        }
+        Ok(self.primary_monitor()?.map(|m| *m.position()))
+    }
+
+    fn relative_position(
+        &self,
+        state: &WindowState,
+    ) -> tauri::Result<Option<PhysicalPosition<i32>>> {
+        let relative = match &state.relative_to {
+            Some(relative) if !state.maximized && !state.fullscreen.is_fullscreen() => relative,
+            _ => return Ok(None),
+        };
+        match self.get_window(&relative.parent) {
+            Some(parent) => {
+                let origin = parent.outer_position()?;
+                Ok(Some(PhysicalPosition::new(
+                    origin.x + relative.offset.x,
+                    origin.y + relative.offset.y,
+                )))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn fills_monitor(&self) -> tauri::Result<bool> {
+        let threshold = match self.state::<PluginConfig>().maximize_detection_threshold {
+            Some(threshold) => threshold,
+            None => return Ok(false),
+        };
+        // fullscreen windows cover their monitor too, but are saved as such
+        if self.is_fullscreen()? {
+            return Ok(false);
+        }
+        let monitor = match self.current_monitor()? {
+            Some(monitor) => monitor,
+            None => return Ok(false),
+        };
+        let window = validation::Rect::new(self.outer_position()?, self.outer_size()?);
+        let covered = validation::covered_fraction(window, validation::Rect::of_monitor(&monitor));
+        Ok(covered >= threshold)
+    }
+
+    fn tracked_flags(&self) -> StateFlags {
+        let categories = self.state::<WindowCategories>();
+        let tags = categories.tags.lock().unwrap();
+        tags.get(self.label())
+            .and_then(|category| categories.flags.get(category))
+            .copied()
+            .unwrap_or_else(|| self.state::<PluginConfig>().state_flags)
+    }
+}
+
+#[derive(Default)]
+pub struct Builder {
+    denylist: HashSet<String>,
+    skip_initial_state: HashSet<String>,
+    state_flags: StateFlags,
+    warning_handler: Option<Box<WarningHandlerFn>>,
+    reapply_after_show: bool,
+    idle_flush: Option<Duration>,
+    proportional_remap: bool,
+    store: Option<Box<dyn StateStore>>,
+    save_delta_threshold: Option<u32>,
+    default_tiling: Option<TilingMode>,
+    filename: Option<String>,
+    primary_relative_coords: bool,
+    skip_duplicate_labels: bool,
+    fit_before_maximize: bool,
+    immediate_flags: Option<StateFlags>,
+    hidden_policy: HiddenPolicy,
+    unknown_in_layout: UnknownPolicy,
+    restore_change_threshold: Option<u32>,
+    cursor_position: Option<Box<CursorPositionFn>>,
+    on_save: Option<Box<OnSaveFn>>,
+    atomic_restore: bool,
+    parallel_restore: bool,
+    event_observer: Option<Arc<EventObserverFn>>,
+    explicitly_placed: HashSet<String>,
+    percentage_positions: bool,
+    always_default: HashMap<String, Option<LogicalSize<f64>>>,
+    restore_interceptor: Option<Box<dyn std::any::Any + Send + Sync>>,
+    window_type_flags: HashMap<String, StateFlags>,
+    continue_on_restore_error: bool,
+    detect_wm_override: bool,
+    reset_on_version_change: Option<String>,
+    move_debounce: Option<Duration>,
+    resize_debounce: Option<Duration>,
+    deferred_restore: Option<Duration>,
+    excluded_monitors: HashSet<String>,
+    maximize_detection_threshold: Option<f64>,
+    legacy_paths: Vec<PathBuf>,
+    live_broadcast: bool,
+    system_default_path: Option<PathBuf>,
+    format: StateFormat,
+    annotations: bool,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a builder from a deserialized configuration, see [`WindowStateConfig`] for its JSON shape.
+    ///
+    /// Options that can't be expressed in the configuration, like handlers and stores,
+    /// can still be set on the returned builder.
+    pub fn from_config(config: WindowStateConfig) -> Self {
+        let mut builder = Self {
+            denylist: config.denylist.into_iter().collect(),
+            skip_initial_state: config.skip_initial_state.into_iter().collect(),
+            reapply_after_show: config.reapply_after_show,
+            proportional_remap: config.proportional_remap,
+            save_delta_threshold: config.save_delta_threshold,
+            default_tiling: config.default_tiling,
+            primary_relative_coords: config.primary_relative_coords,
+            skip_duplicate_labels: config.skip_duplicate_labels,
+            fit_before_maximize: config.fit_before_maximize,
+            immediate_flags: config.immediate_flags,
+            hidden_policy: config.hidden_policy,
+            unknown_in_layout: config.unknown_in_layout,
+            restore_change_threshold: config.restore_change_threshold,
+            atomic_restore: config.atomic_restore,
+            parallel_restore: config.parallel_restore,
+            percentage_positions: config.percentage_positions,
+            continue_on_restore_error: config.continue_on_restore_error,
+            detect_wm_override: config.detect_wm_override,
+            reset_on_version_change: config.reset_on_version_change,
+            excluded_monitors: config.excluded_monitors.into_iter().collect(),
+            maximize_detection_threshold: config.maximize_detection_threshold,
+            legacy_paths: config.legacy_paths,
+            live_broadcast: config.live_broadcast,
+            system_default_path: config.system_default_path,
+            format: config.format,
+            annotations: config.annotations,
+            filename: config.filename,
+            idle_flush: config.idle_flush_ms.map(Duration::from_millis),
+            move_debounce: config
+                .move_debounce_ms
+                .map(Duration::from_millis)
+                .filter(|d| !d.is_zero()),
+            resize_debounce: config
+                .resize_debounce_ms
+                .map(Duration::from_millis)
+                .filter(|d| !d.is_zero()),
+            deferred_restore: config
+                .deferred_restore_ms
+                .map(Duration::from_millis)
+                .filter(|d| !d.is_zero()),
+            ..Default::default()
+        };
+        if let Some(flags) = config.state_flags {
+            builder.state_flags = flags;
+        }
+        builder
+    }
+
+    /// Sets the state flags to control what state gets restored and saved.
+    pub fn with_state_flags(mut self, flags: StateFlags) -> Self {
+        self.state_flags = flags;
+        self
+    }
+
+    /// Sets whether window positions are saved and restored, a shortcut for adding or
+    /// removing [`StateFlags::POSITION`] from the flags set with [`Builder::with_state_flags`].
+    ///
+    /// Without it, windows are placed by the OS. Like [`Builder::with_save_size`], this only
+    /// changes its own flag, and the last call changing a flag wins.
+    pub fn with_save_position(mut self, save: bool) -> Self {
+        self.state_flags.set(StateFlags::POSITION, save);
+        self
+    }
+
+    /// Sets whether window sizes are saved and restored, a shortcut for adding or
+    /// removing [`StateFlags::SIZE`] from the flags set with [`Builder::with_state_flags`].
+    pub fn with_save_size(mut self, save: bool) -> Self {
+        self.state_flags.set(StateFlags::SIZE, save);
+        self
+    }
+
+    /// Sets a list of windows the app creates with an explicit position and size,
+    /// see [`WindowExt::mark_explicitly_placed`].
+    pub fn with_explicitly_placed(mut self, labels: &[&str]) -> Self {
+        self.explicitly_placed = labels.iter().map(|l| l.to_string()).collect();
+        self
+    }
+
+    /// Sets a list of windows that always open centered at their default size,
+    /// for example login dialogs or onboarding wizards.
+    ///
+    /// Unlike [`Builder::with_denylist`], their state is still tracked and saved, but restoring
+    /// ignores it. The default size is the size the app created the window with, unless a size
+    /// was set with [`Builder::with_default_size`].
+    pub fn with_always_default(mut self, labels: &[&str]) -> Self {
+        for label in labels {
+            self.always_default.entry(label.to_string()).or_default();
+        }
+        self
+    }
+
+    /// Sets the logical inner size the window `label` is restored to,
+    /// adding it to the windows set with [`Builder::with_always_default`].
+    pub fn with_default_size(mut self, label: &str, size: LogicalSize<f64>) -> Self {
+        self.always_default.insert(label.into(), Some(size));
+        self
+    }
+
+    /// Sets a list of windows that shouldn't be tracked and managed by this plugin
+    /// for example splash screen windows.
+    pub fn with_denylist(mut self, denylist: &[&str]) -> Self {
+        self.denylist = denylist.iter().map(|l| l.to_string()).collect();
+        self
+    }
+
+    /// Like [`Builder::with_denylist`], but takes any list of labels,
+    /// for example a `Vec<String>` loaded from the app's settings.
+    pub fn with_denylist_iter<I, S>(mut self, denylist: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.denylist = denylist.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Adds the given window label to a list of windows to skip initial state restore.
+    pub fn skip_initial_state(mut self, label: &str) -> Self {
+        self.skip_initial_state.insert(label.into());
+        self
+    }
+
+    /// Sets a handler for non-fatal issues encountered while saving or restoring state,
+    /// for example a stored size that got clamped to fit the current monitors.
+    ///
+    /// By default warnings are logged with [`log::warn!`].
+    pub fn with_warning_handler<F: Fn(Warning) + Send + Sync + 'static>(
+        mut self,
+        handler: F,
+    ) -> Self {
+        self.warning_handler.replace(Box::new(handler));
+        self
+    }
+
+    /// Re-applies the restored position and size shortly after the initial restore,
+    /// correcting windows whose open animation interferes with the first restore.
+    ///
+    /// This is only needed and only has an effect on macOS. Defaults to `false`.
+    pub fn with_reapply_after_show(mut self, reapply: bool) -> Self {
+        self.reapply_after_show = reapply;
+        self
+    }
+
+    /// Saves the state of all windows to disk once no window was moved or resized for `idle`.
+    ///
+    /// This persists changes shortly after the user stops interacting, without writing on every event.
+    /// Unlike a fixed-interval save, nothing is written while the windows are left untouched.
+    /// A pending flush is cancelled on exit in favor of the regular exit save.
+    pub fn with_idle_flush(mut self, idle: Duration) -> Self {
+        self.idle_flush.replace(idle);
+        self
+    }
+
+    /// Saves the state of all windows once a window wasn't moved for `debounce`,
+    /// instead of following [`Builder::with_idle_flush`] for moves.
+    ///
+    /// Dragging a window moves it continuously, so a longer debounce avoids writes mid-drag.
+    /// Changes that exceed [`Builder::with_save_delta_threshold`] are debounced as well.
+    /// Defaults to zero, leaving moves to the other save policies.
+    pub fn with_move_debounce(mut self, debounce: Duration) -> Self {
+        self.move_debounce = Some(debounce).filter(|d| !d.is_zero());
+        self
+    }
+
+    /// Like [`Builder::with_move_debounce`], but for resizes, which often end in a single
+    /// step when a window is snapped to an edge, so a shorter debounce keeps saves responsive.
+    pub fn with_resize_debounce(mut self, debounce: Duration) -> Self {
+        self.resize_debounce = Some(debounce).filter(|d| !d.is_zero());
+        self
+    }
+
+    /// Sets a list of monitors, matched by name, that restoring treats as disconnected,
+    /// for example a TV or projector windows shouldn't reopen on.
+    ///
+    /// A window stored on an excluded monitor is centered on the primary monitor instead,
+    /// or on the first allowed monitor if the primary one is excluded too, and the restore reports
+    /// [`Adjustment::MonitorMismatchFallback`]. Sizes are clamped to the allowed monitors only.
+    /// Monitors without a name can't be excluded. If every connected monitor is excluded,
+    /// the exclusion is ignored and windows restore as if it wasn't set.
+    pub fn with_excluded_monitors(mut self, names: &[&str]) -> Self {
+        self.excluded_monitors = names.iter().map(|n| n.to_string()).collect();
+        self
+    }
+
+    /// Saves windows covering at least `threshold` of their monitor as maximized,
+    /// for window managers that fill the screen without setting the maximized flag.
+    ///
+    /// Such windows keep their last normal size and position and are maximized when restored,
+    /// instead of reopening as a normal window the size of the monitor. Tauri doesn't expose the
+    /// work area, so the fraction is taken of the whole monitor: with a taskbar or dock, a window
+    /// filling the work area covers less than all of it, for example `0.95`, and `threshold`
+    /// has to be lowered accordingly. `threshold` must be greater than `0.0` and at most `1.0`.
+    /// Without this, only windows the runtime reports as maximized are saved as maximized.
+    pub fn with_maximize_detection_threshold(mut self, threshold: f64) -> Self {
+        self.maximize_detection_threshold.replace(threshold);
+        self
+    }
+
+    /// Sets state files of earlier versions of the app, for updates that change the app identifier
+    /// or data directory, and with it the path of the state file.
+    ///
+    /// When the plugin starts without a state file, the first of `paths` that exists is copied
+    /// to the current path, once. The legacy file is copied rather than moved, so it stays
+    /// as a backup, and an existing state file is never overwritten. This only applies to stores
+    /// backed by a file, like the default one.
+    pub fn with_legacy_paths(mut self, paths: &[PathBuf]) -> Self {
+        self.legacy_paths = paths.to_vec();
+        self
+    }
+
+    /// Emits [`STATE_CHANGED_EVENT`] to all windows with the current [`WindowStateChange`]
+    /// of a window while it's moved or resized, for example to draw a live map of all windows.
+    ///
+    /// The event carries the `label` and the `state` of the window, serialized like
+    /// [`WindowState`]. It's emitted at most once per [`Builder::with_move_debounce`] for moves
+    /// and [`Builder::with_resize_debounce`] for resizes, or every 100 milliseconds without them.
+    /// Broadcasting is independent of persistence: it doesn't save the state, and saving doesn't
+    /// broadcast it. Defaults to `false`.
+    pub fn with_live_broadcast(mut self, broadcast: bool) -> Self {
+        self.live_broadcast = broadcast;
+        self
+    }
+
+    /// Sets a read-only layout, for example one installed system-wide by an administrator,
+    /// that seeds the stored state when the user has none yet.
+    ///
+    /// The per-user state file always wins: the layout at `path` is only loaded when no window
+    /// state is stored, on the first run or after [`Builder::with_reset_on_version_change`]
+    /// discarded it, and all changes are saved to the per-user file, never to `path`.
+    /// The layout accepts the same formats as [`AppHandleExt::import_layout_from_file`],
+    /// so a file exported with [`AppHandleExt::export_layout_to_file`] or a hand-written
+    /// JSON object works. A missing or unreadable layout is skipped with a logged warning.
+    pub fn with_system_default_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.system_default_path.replace(path.into());
+        self
+    }
+
+    /// Sets the format the state is written in, to the state file or the store set with
+    /// [`Builder::with_store`], and by [`AppHandleExt::export_layout_to_file`].
+    ///
+    /// Both formats are always read, so changing the format keeps the stored state,
+    /// which is written in the new format on the next save. Defaults to [`StateFormat::Bincode`].
+    pub fn with_format(mut self, format: StateFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Adds a `_comment` field to the top of the state and a `_note` field to every window
+    /// state describing its geometry, like `"800x600 at 100,100 on monitor DELL U2720Q"`,
+    /// to help when inspecting or hand-editing a state written in [`StateFormat::Json`].
+    ///
+    /// The notes are ignored when loading and written anew on every save, so editing them has
+    /// no effect. Does nothing with [`StateFormat::Bincode`]. Defaults to `false`.
+    pub fn with_annotations(mut self, annotations: bool) -> Self {
+        self.annotations = annotations;
+        self
+    }
+
+    /// Restores the initial state of each window `delay` after its webview is ready,
+    /// instead of right away, so restoring doesn't compete with the app's startup work.
+    ///
+    /// Windows first appear at the geometry they're created with and then move to their restored
+    /// geometry, which is visible to the user. To open windows at their restored geometry without
+    /// flicker, create them hidden with [`AppHandleExt::create_restored_window`] instead.
+    /// Windows are tracked right away, but their changes aren't saved until the restore ran,
+    /// so the creation geometry never overwrites the stored state. Defaults to zero, restoring right away.
+    pub fn with_deferred_restore(mut self, delay: Duration) -> Self {
+        self.deferred_restore = Some(delay).filter(|d| !d.is_zero());
+        self
+    }
+
+    /// Remaps the stored geometry proportionally when a window is restored onto a
+    /// monitor other than the one it was saved on.
+    ///
+    /// The stored position and size are expressed as fractions of the saved monitor
+    /// and applied to the target monitor, so a window in the top-right quarter of one
+    /// screen ends up in the top-right quarter of another. The target is the monitor
+    /// with the saved name if it's still connected, otherwise the monitor the window is on.
+    /// Maximized and fullscreen windows aren't remapped. Defaults to `false`.
+    pub fn with_proportional_remap(mut self, remap: bool) -> Self {
+        self.proportional_remap = remap;
+        self
+    }
+
+    /// Sets the store the state is loaded from on startup and saved to.
+    ///
+    /// Defaults to a [`FileStore`] writing [`STATE_FILENAME`], or the name set with
+    /// [`Builder::with_filename`], in the app config directory.
+    /// A custom store, for example backed by SQLite, lets several processes share the window state.
+    pub fn with_store<S: StateStore>(mut self, store: S) -> Self {
+        self.store.replace(Box::new(store));
+        self
+    }
+
+    /// Saves the state of all windows once a window was moved or resized by more than
+    /// `threshold` physical pixels since the last save triggered this way.
+    ///
+    /// Small nudges are only persisted with the next regular save, avoiding disk churn.
+    /// When [`Builder::with_idle_flush`] is set as well, meaningful changes schedule an
+    /// idle flush instead of saving right away.
+    pub fn with_save_delta_threshold(mut self, threshold: u32) -> Self {
+        self.save_delta_threshold.replace(threshold);
+        self
+    }
+
+    /// Arranges windows without a stored state into tiles across the monitor
+    /// when calling [`AppHandleExt::restore_all_windows`], instead of leaving their placement to the OS.
+    pub fn with_default_tiling(mut self, tiling: TilingMode) -> Self {
+        self.default_tiling.replace(tiling);
+        self
+    }
+
+    /// Sets the name of the file the state is saved to in the app config directory.
+    ///
+    /// Defaults to [`STATE_FILENAME`]. Can't be combined with [`Builder::with_store`].
+    pub fn with_filename<S: Into<String>>(mut self, filename: S) -> Self {
+        self.filename.replace(filename.into());
+        self
+    }
+
+    /// Keeps stored positions relative to the origin of the primary monitor instead of
+    /// the absolute virtual desktop coordinates.
+    ///
+    /// On platforms where adding or removing a monitor on the left or top shifts all
+    /// coordinates, this keeps windows in place relative to the primary monitor.
+    /// Windows on other monitors keep their offset from the primary monitor, so they
+    /// only land in the same spot if those monitors kept their arrangement too;
+    /// otherwise the usual fallback for positions outside of all monitors applies.
+    /// Defaults to `false`.
+    pub fn with_primary_relative_coords(mut self, relative: bool) -> Self {
+        self.primary_relative_coords = relative;
+        self
+    }
+
+    /// Doesn't track windows created while another tracked window with the same label is still open.
+    ///
+    /// Such duplicates are always reported with [`Warning::DuplicateLabel`]. By default they are
+    /// tracked anyway and their changes overwrite the state of the first window; with this set,
+    /// only the first window's state is saved. Defaults to `false`.
+    pub fn with_skip_duplicate_labels(mut self, skip: bool) -> Self {
+        self.skip_duplicate_labels = skip;
+        self
+    }
+
+    /// Makes sure the normal size of a window restored as maximized fits the monitor
+    /// it gets maximized on, so un-maximizing it later yields a usable window.
+    ///
+    /// Before maximizing, a normal size larger than that monitor is clamped to it and reported
+    /// with [`Warning::OversizeRestore`], even if it fits on another monitor or
+    /// [`StateFlags::SIZE`] isn't restored. Defaults to `false`.
+    pub fn with_fit_before_maximize(mut self, fit: bool) -> Self {
+        self.fit_before_maximize = fit;
+        self
+    }
+
+    /// Saves the state of all windows to disk right away when a change affects one of `flags`,
+    /// while other changes follow the regular policy, for example [`Builder::with_idle_flush`].
+    ///
+    /// Moves affect [`StateFlags::POSITION`] and resizes [`StateFlags::SIZE`], as well as
+    /// [`StateFlags::MAXIMIZED`] and [`StateFlags::FULLSCREEN`] if they entered or left that mode.
+    /// For example `StateFlags::MAXIMIZED | StateFlags::FULLSCREEN` persists mode changes
+    /// that would be annoying to lose in a crash, without writing on every pixel of a resize.
+    pub fn with_immediate_flags(mut self, flags: StateFlags) -> Self {
+        self.immediate_flags.replace(flags);
+        self
+    }
+
+    /// Sets what restoring a window with [`StateFlags::VISIBLE`] does if the window
+    /// was hidden when its state was saved. Defaults to [`HiddenPolicy::KeepHidden`].
+    ///
+    /// [`HiddenPolicy::Close`] closes the window right after it was created, so the app
+    /// has to tolerate its windows disappearing during the initial restore.
+    pub fn with_hidden_restore_policy(mut self, policy: HiddenPolicy) -> Self {
+        self.hidden_policy = policy;
+        self
+    }
+
+    /// Sets what [`AppHandleExt::restore_from_slot`] does with open windows that aren't part
+    /// of the restored layout, for example because they were opened after it was saved.
+    ///
+    /// Only applies when restoring a layout slot, regular restores are unaffected.
+    /// Defaults to [`UnknownPolicy::LeaveAsIs`].
+    pub fn with_unknown_in_layout(mut self, policy: UnknownPolicy) -> Self {
+        self.unknown_in_layout = policy;
+        self
+    }
+
+    /// Emits [`RESTORED_WITH_CHANGES_EVENT`] when restoring moved or resized a window by more than
+    /// `threshold` physical pixels along any edge or dimension, carrying the geometry before and after.
+    ///
+    /// Helps telling windows the plugin actually moved apart from windows that were already in place.
+    /// No events are emitted without this.
+    pub fn with_restore_change_events(mut self, threshold: u32) -> Self {
+        self.restore_change_threshold.replace(threshold);
+        self
+    }
+
+    /// Opens windows without a stored state on the monitor the cursor is on,
+    /// centered on it, instead of where the app or the OS would place them.
+    ///
+    /// Tauri doesn't expose the cursor position, so `cursor_position` has to locate it,
+    /// for example with a platform API, in physical desktop coordinates.
+    /// If it returns `None` or the cursor isn't on any monitor, the window is left where it is.
+    pub fn with_restore_to_cursor_monitor<
+        F: Fn() -> Option<PhysicalPosition<i32>> + Send + Sync + 'static,
+    >(
+        mut self,
+        cursor_position: F,
+    ) -> Self {
+        self.cursor_position.replace(Box::new(cursor_position));
+        self
+    }
+
+    /// Calls `on_save` with the path and the number of bytes written each time
+    /// the state was saved to a file, for example to push it to a cloud sync.
+    ///
+    /// It is called once the file is completely written, only for stores backed by a file
+    /// like the default [`FileStore`], and not for saves that wrote the same bytes as before.
+    pub fn with_on_save<F: Fn(&Path, usize) + Send + Sync + 'static>(mut self, on_save: F) -> Self {
+        self.on_save.replace(Box::new(on_save));
+        self
+    }
+
+    /// Rolls a window back to the geometry and modes it had before a restore if any step of
+    /// the restore fails, instead of leaving it partially restored.
+    ///
+    /// The rollback is best-effort: it can fail for the same reasons as the restore, and
+    /// window managers may animate or adjust the intermediate steps. Defaults to `false`.
+    pub fn with_atomic_restore(mut self, atomic: bool) -> Self {
+        self.atomic_restore = atomic;
+        self
+    }
+
+    /// Restores the windows concurrently in [`AppHandleExt::restore_all_windows`],
+    /// speeding up apps restoring many windows at once.
+    ///
+    /// Window operations have to be processed by the event loop, so calls on the thread
+    /// running it, like from the app's `setup` or a synchronous command, still restore the
+    /// windows one after another. Defaults to `false`.
+    pub fn with_parallel_restore(mut self, parallel: bool) -> Self {
+        self.parallel_restore = parallel;
+        self
+    }
+
+    /// Calls `observer` with the label and event for every event of a tracked window,
+    /// before the plugin handles it, saving apps from attaching a second event handler.
+    ///
+    /// It runs on the thread delivering window events, so it should return quickly.
+    pub fn with_event_observer<F: Fn(&str, &WindowEvent) + Send + Sync + 'static>(
+        mut self,
+        observer: F,
+    ) -> Self {
+        self.event_observer.replace(Arc::new(observer));
+        self
+    }
+
+    /// Additionally stores positions and sizes as fractions of the monitor a window is on,
+    /// see [`WindowState::fractions`], and restores windows from them.
+    ///
+    /// Unlike [`Builder::with_proportional_remap`], which only remaps windows restored onto a
+    /// different monitor, this always applies the fractions to the monitor with the saved name,
+    /// or the monitor the window is on, so layouts transfer between very different screens.
+    /// Restored positions are rounded to whole physical pixels, so a window may shift by a pixel.
+    /// Maximized and fullscreen windows keep their last fractions. Defaults to `false`.
+    pub fn with_percentage_positions(mut self, percentage: bool) -> Self {
+        self.percentage_positions = percentage;
+        self
+    }
+
+    /// Sets a callback deciding on the geometry of each window with a stored state before it is restored,
+    /// for example to keep a panel docked to an edge.
+    ///
+    /// It is called with the [`RestorePlan`] after the built-in corrections, like clamping
+    /// oversized windows and remapping them between monitors, so changes to the plan
+    /// are applied as they are. Returning `false` cancels the restore of that window,
+    /// which also leaves a hidden window hidden.
+    ///
+    /// The runtime has to match the one the plugin is built for,
+    /// otherwise [`Builder::try_build`] fails.
+    pub fn with_restore_interceptor<
+        R: Runtime,
+        F: Fn(&Window<R>, &mut RestorePlan) -> bool + Send + Sync + 'static,
+    >(
+        mut self,
+        interceptor: F,
+    ) -> Self {
+        let interceptor: Arc<RestoreInterceptorFn<R>> = Arc::new(interceptor);
+        self.restore_interceptor.replace(Box::new(interceptor));
+        self
+    }
+
+    /// Sets the flags used for windows of each category, keyed by the category
+    /// set with [`WindowExt::set_window_category`].
+    ///
+    /// The plugin uses them instead of [`Builder::with_state_flags`] wherever it picks
+    /// the flags itself, like the initial restore and automatic saves. Flags passed to
+    /// methods like [`AppHandleExt::save_window_state`] are used as given.
+    /// A window's [`WindowExt::set_state_persistence`] override takes precedence over its category.
+    pub fn with_window_type_flags(mut self, flags: HashMap<String, StateFlags>) -> Self {
+        self.window_type_flags = flags;
+        self
+    }
+
+    /// Sets whether restoring keeps applying the remaining properties when setting one fails,
+    /// for runtimes that don't support every window operation.
+    ///
+    /// Either way, each failing setter is reported as [`Warning::RestoreSetterFailed`].
+    /// By default, restoring stops and returns the first error.
+    pub fn with_continue_on_restore_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_restore_error = continue_on_error;
+        self
+    }
+
+    /// Sets whether to check if the window manager placed a window elsewhere
+    /// right after its geometry was restored, as tiling window managers do.
+    ///
+    /// Half a second after each restore, the geometry is read again. If an edge or dimension
+    /// moved by more than 32 physical pixels, a [`Warning::WindowManagerOverride`] is emitted
+    /// and the window stops being saved, leaving its placement to the window manager, unless
+    /// the app turned persistence on with [`WindowExt::set_state_persistence`]. Maximized and
+    /// fullscreen restores aren't checked. Defaults to `false`.
+    pub fn with_detect_wm_override(mut self, detect: bool) -> Self {
+        self.detect_wm_override = detect;
+        self
+    }
+
+    /// Discards the stored state of all windows once when the app runs with a `version`
+    /// different from the one it last ran with, so a new release can ship a curated default layout.
+    ///
+    /// The version is stored in the state file. When it differs, all windows open at the
+    /// geometry the app creates them with for that launch, and the new version is recorded.
+    /// A state file without a version, like one written before this was set, counts as an
+    /// older version. Layouts saved with [`AppHandleExt::save_to_slot`] are kept.
+    pub fn with_reset_on_version_change<S: Into<String>>(mut self, version: S) -> Self {
+        self.reset_on_version_change.replace(version.into());
+        self
+    }
+
+    /// Builds the plugin, panicking if the configuration is invalid.
+    ///
+    /// Use [`Builder::try_build`] to handle configuration errors instead.
+    pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
+        self.try_build()
+            .expect("invalid window-state plugin configuration")
+    }
+
+    /// Builds the plugin, failing if the configuration is invalid.
+    pub fn try_build<R: Runtime>(mut self) -> std::result::Result<TauriPlugin<R>, BuilderError> {
+        if let Some(filename) = &self.filename {
+            if filename.is_empty() {
+                return Err(BuilderError::EmptyFilename);
+            }
+            if Path::new(filename).file_name() != Some(std::ffi::OsStr::new(filename)) {
+                return Err(BuilderError::InvalidFilename(filename.clone()));
+            }
+            if self.store.is_some() {
+                return Err(BuilderError::FilenameWithStore);
+            }
+        }
+        if self.idle_flush == Some(Duration::ZERO) {
+            return Err(BuilderError::ZeroIdleFlush);
+        }
+        if let Some(threshold) = self.maximize_detection_threshold {
+            if !(threshold > 0. && threshold <= 1.) {
+                return Err(BuilderError::InvalidMaximizeThreshold(threshold));
+            }
+        }
+        let restore_interceptor = match self.restore_interceptor.take() {
+            Some(interceptor) => Some(
+                *interceptor
+                    .downcast::<Arc<RestoreInterceptorFn<R>>>()
+                    .map_err(|_| BuilderError::InterceptorRuntimeMismatch)?,
+            ),
+            None => None,
+        };
+
+        let flags = self.state_flags;
+        let filename = self
+            .filename
+            .take()
+            .unwrap_or_else(|| STATE_FILENAME.into());
+        let warning_handler = self.warning_handler.take();
+        let cursor_position = self.cursor_position.take();
+        let on_save = self.on_save.take();
+        let explicitly_placed = std::mem::take(&mut self.explicitly_placed);
+        let always_default = std::mem::take(&mut self.always_default);
+        let reset_on_version_change = self.reset_on_version_change.take();
+        let window_type_flags = std::mem::take(&mut self.window_type_flags);
+        let idle_flush = self.idle_flush;
+        let store = self.store.take();
+        let legacy_paths = std::mem::take(&mut self.legacy_paths);
+        let system_default_path = self.system_default_path.take();
+        let config = PluginConfig {
+            state_flags: flags,
+            proportional_remap: self.proportional_remap,
+            default_tiling: self.default_tiling,
+            primary_relative_coords: self.primary_relative_coords,
+            fit_before_maximize: self.fit_before_maximize,
+            hidden_policy: self.hidden_policy,
+            unknown_in_layout: self.unknown_in_layout,
+            restore_change_threshold: self.restore_change_threshold,
+            atomic_restore: self.atomic_restore,
+            parallel_restore: self.parallel_restore,
+            percentage_positions: self.percentage_positions,
+            continue_on_restore_error: self.continue_on_restore_error,
+            detect_wm_override: self.detect_wm_override,
+            excluded_monitors: self.excluded_monitors,
+            maximize_detection_threshold: self.maximize_detection_threshold,
+            format: self.format,
+            annotations: self.annotations,
+            auto_show: AtomicBool::new(true),
+        };
+        let (format, annotations) = (self.format, self.annotations);
+        Ok(PluginBuilder::new("window-state")
+            .invoke_handler(tauri::generate_handler![
+                cmd::save_window_state,
+                cmd::restore_state,
+                cmd::clear_all_window_states,
+                cmd::get_config,
+                cmd::capture_current_layout,
+                cmd::commit_layout,
+                cmd::get_all_window_states
+            ])
+            .setup(move |app| {
+                // loading the state may already emit warnings
+                app.manage(WarningHandler(warning_handler));
+                let store = store.or_else(|| {
+                    app.path_resolver().app_config_dir().map(|app_dir| {
+                        Box::new(FileStore::new(app_dir.join(filename))) as Box<dyn StateStore>
+                    })
+                });
+                let path = store.as_ref().and_then(|store| store.path());
+                if let Some(path) = path.filter(|_| !legacy_paths.is_empty()) {
+                    match migrate_legacy_state(path, &legacy_paths) {
+                        Ok(Some(legacy)) => log::info!(
+                            "migrated the window state from {} to {}",
+                            legacy.display(),
+                            path.display()
+                        ),
+                        Ok(None) => {}
+                        Err(e) => log::warn!("failed to migrate the legacy window state: {}", e),
+                    }
+                }
+                let loaded = store.as_ref().map(|store| read_state(store.as_ref()));
+                // a file that failed to load, like one of a newer version, is left untouched
+                let load_failed = matches!(loaded, Some(Err(_)));
+                let (mut windows, mut metadata) = match loaded {
+                    Some(Ok(mut state)) => {
+                        sanitize_state(app, &mut state);
+                        (state.windows, state.metadata)
+                    }
+                    Some(Err(e)) => {
+                        log::warn!("failed to load the window state: {}", e);
+                        Default::default()
+                    }
+                    None => Default::default(),
+                };
+                if let Some(version) = reset_on_version_change {
+                    if metadata.app_version.as_ref() != Some(&version) {
+                        windows.clear();
+                        metadata.app_version = Some(version);
+                    }
+                }
+                if let Some(path) = system_default_path.filter(|_| windows.is_empty()) {
+                    let layout = tauri::api::file::read_binary(&path)
+                        .map_err(Error::TauriApi)
+                        .and_then(|bytes| deserialize_state(&bytes));
+                    match layout {
+                        Ok(mut layout) => {
+                            sanitize_state(app, &mut layout);
+                            windows = layout.windows;
+                        }
+                        Err(e) => log::warn!(
+                            "failed to load the default window state from {}: {}",
+                            path.display(),
+                            e
+                        ),
+                    }
+                }
+                let mut previously_open: Vec<String> = windows
+                    .iter()
+                    .filter(|(key, state)| !key.contains('@') && state.visible)
+                    .map(|(label, _)| label.clone())
+                    .collect();
+                previously_open.sort();
+                app.manage(PreviouslyOpen(previously_open));
+                app.manage(LastExitClean(metadata.clean_exit));
+                // mark the run as unclean until the exit save, a read-only directory just loses the mark
+                metadata.clean_exit = Some(false);
+                if let Some(store) = store.as_ref().filter(|_| !load_failed) {
+                    if let Err(e) = encode_persisted(&windows, &metadata, format, annotations)
+                        .and_then(|bytes| store.save(&bytes))
+                    {
+                        log::debug!("failed to mark the window state as in use: {}", e);
+                    }
+                }
+                let cache: Arc<Mutex<HashMap<String, WindowState>>> = Arc::new(Mutex::new(windows));
+                app.manage(LoadedLabels(Mutex::new(
+                    cache.lock().unwrap().keys().cloned().collect(),
+                )));
+                app.manage(WindowStateCache(cache));
+                app.manage(Metadata(Mutex::new(metadata)));
+                app.manage(Store(Mutex::new(store)));
+                app.manage(ProgrammaticWindows::default());
+                app.manage(PersistencePreferences::default());
+                app.manage(SavePaused::default());
+                app.manage(Stashes::default());
+                app.manage(LiveWindows::default());
+                app.manage(EventLoopThread(std::thread::current().id()));
+                app.manage(RestoredBeforeShow::default());
+                app.manage(CursorPosition(cursor_position));
+                app.manage(OnSave(on_save, Mutex::new(None)));
+                app.manage(ExplicitlyPlaced(Mutex::new(explicitly_placed)));
+                app.manage(AlwaysDefault(always_default));
+                app.manage(WindowCategories {
+                    flags: window_type_flags,
+                    tags: Default::default(),
+                });
+                app.manage(RestoreInterceptor(restore_interceptor));
+                app.manage(Subscribers::default());
+                app.manage(config);
+
+                let (save_tx, rx) = channel();
+                let worker_app = app.clone();
+                std::thread::spawn(move || save_worker(worker_app, rx));
+                app.manage(SaveWorker {
+                    tx: Mutex::new(Some(save_tx)),
+                    default: idle_flush,
+                    overrides: Default::default(),
+                });
+                Ok(())
+            })
+            .on_webview_ready(move |window| {
+                let preferences = window.state::<PersistencePreferences>().0.clone();
+                // window-level preferences take precedence over the builder-level denylist
+                let tracked = preferences
+                    .lock()
+                    .unwrap()
+                    .get(window.label())
+                    .copied()
+                    .unwrap_or_else(|| !self.denylist.contains(window.label()));
+                if !tracked {
+                    return;
+                }
+
+                let live = window.state::<LiveWindows>().inner().clone();
+                let duplicate = live.open(window.label());
+                let label = window.label().to_string();
+                window.on_window_event(move |e| {
+                    if let WindowEvent::Destroyed = e {
+                        live.close(&label);
+                    }
+                });
+                if duplicate {
+                    emit_warning(
+                        &window,
+                        Warning::DuplicateLabel {
+                            label: window.label().into(),
+                        },
+                    );
+                    if self.skip_duplicate_labels {
+                        return;
+                    }
+                }
+
+                let restored_before_show = window
+                    .state::<RestoredBeforeShow>()
+                    .0
+                    .lock()
+                    .unwrap()
+                    .remove(window.label());
+                let explicitly_placed = window
+                    .state::<ExplicitlyPlaced>()
+                    .0
+                    .lock()
+                    .unwrap()
+                    .contains(window.label());
+                let tracked_flags = window.tracked_flags();
+                let initial_flags = match explicitly_placed {
+                    true => tracked_flags - (StateFlags::SIZE | StateFlags::POSITION),
+                    false => tracked_flags,
+                };
+                let skip_initial = self.skip_initial_state.contains(window.label());
+                let deferred = self
+                    .deferred_restore
+                    .filter(|_| !restored_before_show && !skip_initial);
+                let restored = restored_before_show
+                    || (!skip_initial
+                        && deferred.is_none()
+                        && window.restore_state(initial_flags).is_ok());
+                if restored {
+                    if cfg!(target_os = "macos") && self.reapply_after_show {
+                        let window = window.clone();
+                        let flags = initial_flags & (StateFlags::SIZE | StateFlags::POSITION);
+                        std::thread::spawn(move || {
+                            std::thread::sleep(REAPPLY_AFTER_SHOW_DELAY);
+                            // re-applying the normal geometry would undo these modes
+                            if !window.is_maximized().unwrap_or(false)
+                                && !window.is_fullscreen().unwrap_or(false)
+                            {
+                                let _ = window.restore_state(flags);
+                            }
+                        });
+                    }
+                }
+
+                let cache = window.state::<WindowStateCache>();
+                let cache = cache.0.clone();
+                let programmatic = window.state::<ProgrammaticWindows>().0.clone();
+                let label = window.label().to_string();
+                let window_clone = window.clone();
+                let flags = tracked_flags;
+                let save_delta_threshold = self.save_delta_threshold;
+                let last_saved_geometry = Mutex::new(Geometry::of(&window).ok());
+                let immediate_flags = self.immediate_flags.unwrap_or_else(StateFlags::empty);
+                // the last seen maximized and fullscreen modes, to tell which flags a resize affects
+                let last_modes = Mutex::new((
+                    window.is_maximized().unwrap_or(false),
+                    window.is_fullscreen().unwrap_or(false),
+                ));
+
+                // insert a default state if this window should be tracked and
+                // the disk cache doesn't have a state for it, a successful restore already did
+                if !restored && deferred.is_none() {
+                    cache
+                        .lock()
+                        .unwrap()
+                        .entry(label.clone())
+                        .or_insert_with(WindowState::default);
+                }
+
+                let event_observer = self.event_observer.clone();
+                let save_paused = window.state::<SavePaused>().flag(window.label());
+                let move_debounce = self.move_debounce;
+                let resize_debounce = self.resize_debounce;
+                let live_broadcast = self.live_broadcast;
+                // the creation geometry must not be saved over the stored state before a deferred restore
+                let restore_pending = Arc::new(AtomicBool::new(deferred.is_some()));
+                let deferred_restore = deferred.map(|delay| {
+                    (
+                        delay,
+                        window.clone(),
+                        cache.clone(),
+                        restore_pending.clone(),
+                    )
+                });
+
+                window.on_window_event(move |e| {
+                    if let Some(observer) = &event_observer {
+                        observer(&label, e);
+                    }
+
+                    if save_paused.load(Ordering::Relaxed)
+                        || restore_pending.load(Ordering::Relaxed)
+                        || programmatic.lock().unwrap().contains_key(&label)
+                        || preferences.lock().unwrap().get(&label) == Some(&false)
+                    {
+                        return;
+                    }
+
+                    match e {
+                        WindowEvent::CloseRequested { .. } => {
+                            // read the window before locking, like every other state update
+                            let parent = match cache.lock().unwrap().get(&label) {
+                                Some(state) => state.relative_to.as_ref().map(|r| r.parent.clone()),
+                                None => return,
+                            };
+                            if let Ok(properties) =
+                                window_clone.read_properties(flags, parent.as_deref())
+                            {
+                                let percentage_positions =
+                                    window_clone.state::<PluginConfig>().percentage_positions;
+                                let mut c = cache.lock().unwrap();
+                                if let Some(state) = c.get_mut(&label) {
+                                    merge_properties(
+                                        state,
+                                        &properties,
+                                        flags,
+                                        percentage_positions,
+                                    );
+                                    window_clone.state::<Subscribers>().notify(&label, state);
+                                }
+                            }
+                        }
+
+                        WindowEvent::Focused(true) => {
+                            let monitor = window_clone
+                                .current_monitor()
+                                .ok()
+                                .flatten()
+                                .and_then(|m| m.name().cloned());
+                            let metadata = window_clone.state::<Metadata>();
+                            let mut metadata = metadata.0.lock().unwrap();
+                            metadata.focused_label = Some(label.clone());
+                            if let Some(monitor) = monitor {
+                                metadata.focused_per_monitor.insert(monitor, label.clone());
+                            }
+                        }
+
+                        WindowEvent::Moved(position) if flags.contains(StateFlags::POSITION) => {
+                            let origin = window_clone.primary_origin().ok().flatten();
+                            let mut c = cache.lock().unwrap();
+                            if let Some(origin) = origin {
+                                let metadata = window_clone.state::<Metadata>();
+                                rebase_positions(&mut c, &mut metadata.0.lock().unwrap(), origin);
+                            }
+                            if let Some(state) = c.get_mut(&label) {
+                                state.prev_x = state.x;
+                                state.prev_y = state.y;
+
+                                state.x = position.x;
+                                state.y = position.y;
+                                window_clone.state::<Subscribers>().notify(&label, state);
+                            }
+                        }
+                        _ => {}
+                    }
+
+                    let changed = match e {
+                        WindowEvent::Moved(_) => StateFlags::POSITION,
+                        WindowEvent::Resized(_) => {
+                            let modes = (
+                                window_clone.is_maximized().unwrap_or(false),
+                                window_clone.is_fullscreen().unwrap_or(false),
+                            );
+                            let mut last_modes = last_modes.lock().unwrap();
+                            let mut changed = StateFlags::SIZE;
+                            changed.set(StateFlags::MAXIMIZED, modes.0 != last_modes.0);
+                            changed.set(StateFlags::FULLSCREEN, modes.1 != last_modes.1);
+                            *last_modes = modes;
+                            changed
+                        }
+                        _ => return,
+                    };
+                    let kind = match e {
+                        WindowEvent::Moved(_) => ChangeKind::Moved,
+                        _ => ChangeKind::Resized,
+                    };
+
+                    if live_broadcast {
+                        let interval = match e {
+                            WindowEvent::Moved(_) => move_debounce,
+                            _ => resize_debounce,
+                        };
+                        window_clone
+                            .state::<SaveWorker>()
+                            .broadcast(&label, interval.unwrap_or(LIVE_BROADCAST_INTERVAL));
+                    }
+
+                    if changed.intersects(immediate_flags) {
+                        if let Ok(geometry) = Geometry::of(&window_clone) {
+                            last_saved_geometry.lock().unwrap().replace(geometry);
+                        }
+                        window_clone.state::<SaveWorker>().mark_dirty(&label, kind);
+                        return;
+                    }
+
+                    let worker = window_clone.state::<SaveWorker>();
+                    let debounce = match kind {
+                        ChangeKind::Moved => move_debounce,
+                        ChangeKind::Resized => resize_debounce,
+                    };
+                    match save_delta_threshold {
+                        Some(threshold) => {
+                            let geometry = match Geometry::of(&window_clone) {
+                                Ok(geometry) => geometry,
+                                Err(_) => return,
+                            };
+                            let exceeded = {
+                                let mut last = last_saved_geometry.lock().unwrap();
+                                let exceeded =
+                                    last.map_or(true, |last| last.delta(&geometry) > threshold);
+                                if exceeded {
+                                    last.replace(geometry);
+                                }
+                                exceeded
+                            };
+                            if exceeded {
+                                match debounce {
+                                    Some(delay) => worker.debounce(&label, kind, delay),
+                                    None if worker.is_enabled(&label) => {
+                                        worker.notify(&label, kind)
+                                    }
+                                    None => worker.mark_dirty(&label, kind),
+                                }
+                            }
+                        }
+                        None => match debounce {
+                            Some(delay) => worker.debounce(&label, kind, delay),
+                            None => worker.notify(&label, kind),
+                        },
+                    }
+                });
+
+                if let Some((delay, window, cache, pending)) = deferred_restore {
+                    std::thread::spawn(move || {
+                        std::thread::sleep(delay);
+                        if window.restore_state(initial_flags).is_err() {
+                            cache
+                                .lock()
+                                .unwrap()
+                                .entry(window.label().into())
+                                .or_insert_with(WindowState::default);
+                        }
+                        pending.store(false, Ordering::Relaxed);
+                    });
+                }
+            })
+            .on_event(move |app, event| {
+                if let RunEvent::Exit = event {
+                    app.state::<SaveWorker>().stop();
+                    app.state::<Metadata>().0.lock().unwrap().clean_exit = Some(true);
+                    let _ = save_tracked_state(app);
+                }
+            })
+            .build())
+    }
+}
+
+/// The window operations restoring needs, so the restore logic
+/// works on plain [`MonitorInfo`] values instead of a real window.
+trait WindowOps {
+    /// The monitors available to the window.
+    fn monitors(&self) -> tauri::Result<Vec<MonitorInfo>>;
+    /// The connected monitors left out with [`Builder::with_excluded_monitors`].
+    fn excluded_monitors(&self) -> tauri::Result<Vec<MonitorInfo>>;
+    /// The primary monitor, if any.
+    fn primary(&self) -> tauri::Result<Option<MonitorInfo>>;
+    /// The monitor the window is currently on, if any.
+    fn monitor(&self) -> tauri::Result<Option<MonitorInfo>>;
+    /// The inner size, in logical pixels of the monitor the window is on.
+    fn logical_size(&self) -> tauri::Result<LogicalSize<f64>>;
+    /// The size of the decorations, the outer size minus the inner size.
+    fn decorations_size(&self) -> tauri::Result<PhysicalSize<u32>>;
+    fn geometry(&self) -> tauri::Result<Geometry>;
+    fn is_visible(&self) -> tauri::Result<bool>;
+    fn is_maximized(&self) -> tauri::Result<bool>;
+    fn is_fullscreen(&self) -> tauri::Result<bool>;
+    fn is_decorated(&self) -> tauri::Result<bool>;
+    fn set_logical_size(&self, size: LogicalSize<f64>) -> tauri::Result<()>;
+    fn set_physical_size(&self, size: PhysicalSize<u32>) -> tauri::Result<()>;
+    fn set_physical_position(&self, position: PhysicalPosition<i32>) -> tauri::Result<()>;
+    fn set_decorations(&self, decorated: bool) -> tauri::Result<()>;
+    fn set_fullscreen(&self, fullscreen: bool) -> tauri::Result<()>;
+    fn maximize(&self) -> tauri::Result<()>;
+    fn unmaximize(&self) -> tauri::Result<()>;
+    fn center(&self) -> tauri::Result<()>;
+    fn show(&self) -> tauri::Result<()>;
+    fn set_focus(&self) -> tauri::Result<()>;
+}
+
+/// The monitors restoring considers, leaving out the ones set with
+/// [`Builder::with_excluded_monitors`] unless that leaves none.
+fn restore_monitors<R: Runtime>(window: &Window<R>) -> tauri::Result<Vec<Monitor>> {
+    let monitors = window.available_monitors()?;
+    let excluded = &window.state::<PluginConfig>().excluded_monitors;
+    let allowed: Vec<Monitor> = monitors
+        .iter()
+        .filter(|m| !m.name().map_or(false, |n| excluded.contains(n)))
+        .cloned()
+        .collect();
+    match allowed.is_empty() {
+        true => Ok(monitors),
+        false => Ok(allowed),
+    }
+}
+
+impl<R: Runtime> WindowOps for Window<R> {
+    fn monitors(&self) -> tauri::Result<Vec<MonitorInfo>> {
+        Ok(restore_monitors(self)?.iter().map(Into::into).collect())
+    }
+
+    fn excluded_monitors(&self) -> tauri::Result<Vec<MonitorInfo>> {
+        let excluded = &self.state::<PluginConfig>().excluded_monitors;
+        if excluded.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .available_monitors()?
+            .iter()
+            .filter(|m| m.name().map_or(false, |n| excluded.contains(n)))
+            .map(Into::into)
+            .collect())
+    }
+
+    fn primary(&self) -> tauri::Result<Option<MonitorInfo>> {
+        Ok(self.primary_monitor()?.as_ref().map(Into::into))
+    }
+
+    fn monitor(&self) -> tauri::Result<Option<MonitorInfo>> {
+        Ok(self.current_monitor()?.as_ref().map(Into::into))
+    }
+
+    fn logical_size(&self) -> tauri::Result<LogicalSize<f64>> {
+        let scale_factor = self
+            .current_monitor()?
+            .map(|m| m.scale_factor())
+            .unwrap_or(1.);
+        Ok(self.inner_size()?.to_logical(scale_factor))
+    }
+
+    fn decorations_size(&self) -> tauri::Result<PhysicalSize<u32>> {
+        let outer = self.outer_size()?;
+        let inner = self.inner_size()?;
+        Ok(PhysicalSize::new(
+            outer.width.saturating_sub(inner.width),
+            outer.height.saturating_sub(inner.height),
+        ))
+    }
+
+    fn geometry(&self) -> tauri::Result<Geometry> {
+        Geometry::of(self)
+    }
+
+    fn is_visible(&self) -> tauri::Result<bool> {
+        Window::is_visible(self)
+    }
+
+    fn is_maximized(&self) -> tauri::Result<bool> {
+        Window::is_maximized(self)
+    }
+
+    fn is_fullscreen(&self) -> tauri::Result<bool> {
+        Window::is_fullscreen(self)
+    }
+
+    fn is_decorated(&self) -> tauri::Result<bool> {
+        Window::is_decorated(self)
+    }
+
+    fn set_logical_size(&self, size: LogicalSize<f64>) -> tauri::Result<()> {
+        self.set_size(size)
+    }
+
+    fn set_physical_size(&self, size: PhysicalSize<u32>) -> tauri::Result<()> {
+        self.set_size(size)
+    }
+
+    fn set_physical_position(&self, position: PhysicalPosition<i32>) -> tauri::Result<()> {
+        self.set_position(position)
+    }
+
+    fn set_decorations(&self, decorated: bool) -> tauri::Result<()> {
+        Window::set_decorations(self, decorated)
+    }
+
+    fn set_fullscreen(&self, fullscreen: bool) -> tauri::Result<()> {
+        Window::set_fullscreen(self, fullscreen)
+    }
+
+    fn maximize(&self) -> tauri::Result<()> {
+        Window::maximize(self)
+    }
+
+    fn unmaximize(&self) -> tauri::Result<()> {
+        Window::unmaximize(self)
+    }
+
+    fn center(&self) -> tauri::Result<()> {
+        Window::center(self)
+    }
+
+    fn show(&self) -> tauri::Result<()> {
+        Window::show(self)
+    }
+
+    fn set_focus(&self) -> tauri::Result<()> {
+        Window::set_focus(self)
+    }
+}
+
+/// The size to restore `state` to, clamped to a monitor if it doesn't fit on any, unless
+/// all the monitors a spanning window was saved across are still connected.
+/// Also returns the monitor the size was clamped to, if it was.
+fn restored_size(
+    window: &dyn WindowOps,
+    state: &WindowState,
+) -> tauri::Result<(LogicalSize<f64>, Option<MonitorInfo>)> {
+    let mut size = LogicalSize {
+        width: state.width,
+        height: state.height,
+    };
+
+    // clamp sizes that can't fit on any of the available monitors
+    // to the monitor the window is going to be restored on, unless the window
+    // spanned several monitors that are all still connected
+    let monitors = window.monitors()?;
+    let spans_connected_monitors = !state.spanned_monitors.is_empty()
+        && state.spanned_monitors.iter().all(|name| {
+            monitors
+                .iter()
+                .any(|m| m.name.as_ref().map_or(false, |n| n == name))
+        });
+    if monitors.is_empty() || spans_connected_monitors || monitors.iter().any(|m| m.fits(size)) {
+        return Ok((size, None));
+    }
+
+    let position = (state.x, state.y).into();
+    let monitor = match monitors
+        .iter()
+        .find(|m| m.intersects(position, size.cast()))
+    {
+        Some(m) => Some(m.clone()),
+        None => window.monitor()?,
+    };
+    if let Some(monitor) = &monitor {
+        let bounds = PhysicalSize::new(monitor.width, monitor.height)
+            .to_logical::<f64>(monitor.scale_factor);
+        size.width = size.width.min(bounds.width);
+        size.height = size.height.min(bounds.height);
+    }
+    Ok((size, monitor))
+}
+
+/// The position to restore `state` to, `None` if it isn't on any monitor.
+fn restored_position(
+    window: &dyn WindowOps,
+    state: &WindowState,
+) -> tauri::Result<Option<PhysicalPosition<i32>>> {
+    let position = (state.x, state.y).into();
+    let size = (state.width, state.height).into();
+    // restore position to saved value if saved monitor exists
+    // otherwise, let the OS decide where to place the window
+    if window
+        .monitors()?
+        .iter()
+        .any(|m| m.intersects(position, size))
+    {
+        Ok(Some(PhysicalPosition {
+            x: if state.maximized {
+                state.prev_x
+            } else {
+                state.x
+            },
+            y: if state.maximized {
+                state.prev_y
+            } else {
+                state.y
+            },
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Sets the size, then the position, skipping the ones that are `None`.
+fn set_geometry(
+    window: &dyn WindowOps,
+    size: Option<LogicalSize<f64>>,
+    position: Option<PhysicalPosition<i32>>,
+) -> tauri::Result<()> {
+    if let Some(size) = size {
+        window.set_logical_size(size)?;
+    }
+    if let Some(position) = position {
+        window.set_physical_position(position)?;
+    }
+    Ok(())
+}
+
+/// Plans restoring `state` with `flags` on `window`, remapping it onto the connected monitors
+/// first. `relative` is the position relative to the open parent window, if any.
+fn plan_geometry(
+    window: &dyn WindowOps,
+    state: &mut WindowState,
+    flags: StateFlags,
+    options: RestoreOptions,
+    relative: Option<PhysicalPosition<i32>>,
+) -> tauri::Result<RestorePlan> {
+    let mut adjustments = Vec::new();
+
+    // maximized and fullscreen windows fill their monitor regardless of the stored geometry
+    let normal = !state.maximized && !state.fullscreen.is_fullscreen();
+    let fractions = state
+        .fractions
+        .filter(|_| normal && options.percentage_positions);
+    if fractions.is_some() || (normal && options.proportional_remap) {
+        if let Some(target) = remap_target(window, state)? {
+            if let Some(stored) = state.monitor.as_ref().and_then(|m| m.name.clone()) {
+                if target.name.as_ref() != Some(&stored) {
+                    adjustments.push(Adjustment::MonitorMismatchFallback {
+                        stored,
+                        used: target.name.clone(),
+                    });
+                }
+            }
+            match fractions {
+                Some(fractions) => state.apply_fractions(fractions, &target),
+                None => state.remap_to(&target),
+            }
+        }
+    }
+
+    let size = match flags.contains(StateFlags::SIZE) {
+        true => {
+            let (size, clamped_on) = restored_size(window, state)?;
+            if let Some(monitor) = clamped_on {
+                adjustments.push(Adjustment::OversizeReduced {
+                    monitor: monitor.name,
+                    requested: LogicalSize {
+                        width: state.width,
+                        height: state.height,
+                    },
+                    clamped: size,
+                });
+            }
+            Some(size)
+        }
+        false => None,
+    };
+    let position = match flags.contains(StateFlags::POSITION) {
+        true => match relative {
+            Some(position) => Some(position),
+            None => match restored_position(window, state)? {
+                Some(position) => Some(position),
+                None => match excluded_fallback(window, state, size)? {
+                    Some((stored, used, position)) => {
+                        adjustments.push(Adjustment::MonitorMismatchFallback {
+                            stored,
+                            used: used.name,
+                        });
+                        Some(position)
+                    }
+                    None => {
+                        adjustments.push(Adjustment::OffscreenDiscarded);
+                        None
+                    }
+                },
+            },
+        },
+        false => None,
+    };
+    // windows placed relative to their parent follow it instead of their monitor
+    let snap = state
+        .snap
+        .filter(|_| normal && flags.contains(StateFlags::SNAP) && relative.is_none());
+    if let Some(snap) = snap {
+        adjustments.push(Adjustment::Snapped { snap });
+    }
+
+    Ok(RestorePlan {
+        size,
+        position,
+        snap,
+        maximize: flags.contains(StateFlags::MAXIMIZED) && state.maximized,
+        // the runtime has no notion of exclusive fullscreen, so both modes use it
+        fullscreen: flags
+            .contains(StateFlags::FULLSCREEN)
+            .then(|| state.fullscreen.is_fullscreen()),
+        adjustments,
+    })
+}
+
+/// Applies `plan` for `state` to `window`, reporting failed setters of the window labeled `label`
+/// through `warn`. Returns the geometry to apply again once the window is shown, for windows
+/// restored in their normal mode.
+fn apply_plan(
+    window: &dyn WindowOps,
+    label: &str,
+    state: &WindowState,
+    plan: &mut RestorePlan,
+    flags: StateFlags,
+    options: RestoreOptions,
+    warn: &dyn Fn(Warning),
+) -> tauri::Result<Option<(Option<LogicalSize<f64>>, Option<PhysicalPosition<i32>>)>> {
+    let check = |setter: &'static str, result: tauri::Result<()>| match result {
+        Err(e) => {
+            warn(Warning::RestoreSetterFailed {
+                label: label.into(),
+                setter,
+                error: e.to_string(),
+            });
+            match options.continue_on_error {
+                true => Ok(()),
+                false => Err(e),
+            }
+        }
+        Ok(()) => Ok(()),
+    };
+
+    if flags.contains(StateFlags::DECORATIONS) {
+        check("set_decorations", window.set_decorations(state.decorated))?;
+    }
+
+    // a zero normal size would leave nothing sensible to un-maximize to
+    let invalid_normal = plan.maximize && !(state.width > 0. && state.height > 0.);
+    if invalid_normal {
+        plan.size = None;
+        plan.position = None;
+    }
+
+    let mut geometry = None;
+    check("set_size", set_geometry(window, plan.size, None))?;
+    check("set_position", set_geometry(window, None, plan.position))?;
+    if !plan.maximize && plan.fullscreen != Some(true) {
+        geometry = Some((plan.size, plan.position));
+        if let Some(snap) = plan.snap {
+            check("snap", apply_snap(window, snap))?;
+        }
+    }
+
+    if plan.maximize {
+        if let Some(name) = &state.maximized_monitor {
+            check("set_position", move_to_monitor(window, name))?;
+        }
+        if invalid_normal {
+            check("set_size", substitute_normal_size(window, label, warn))?;
+        }
+        if options.fit_before_maximize {
+            check("set_size", fit_normal_size(window, label, plan.size, warn))?;
+        }
+        check("maximize", window.maximize())?;
+    }
+
+    if let Some(fullscreen) = plan.fullscreen {
+        check("set_fullscreen", window.set_fullscreen(fullscreen))?;
+    }
+    Ok(geometry)
+}
+
+/// Shows and focuses `window`, then applies `geometry` again, for windows that were hidden
+/// while being restored, see [`REAPPLY_GEOMETRY_AFTER_SHOW`].
+fn show_and_reapply(
+    window: &dyn WindowOps,
+    geometry: Option<(Option<LogicalSize<f64>>, Option<PhysicalPosition<i32>>)>,
+) -> tauri::Result<()> {
+    window.show()?;
+    window.set_focus()?;
+    if let Some((size, position)) = geometry {
+        set_geometry(window, size, position)?;
+    }
+    Ok(())
+}
+
+/// Picks the monitor stored geometry gets remapped onto: the monitor with the saved name,
+/// otherwise the monitor the window is currently on, otherwise the primary monitor.
+fn remap_target(window: &dyn WindowOps, state: &WindowState) -> tauri::Result<Option<MonitorInfo>> {
+    let name = state.monitor.as_ref().and_then(|m| m.name.as_ref());
+    let monitors = window.monitors()?;
+    if let Some(m) = monitors
+        .iter()
+        .find(|m| name.is_some() && m.name.as_ref() == name)
+    {
+        return Ok(Some(m.clone()));
+    }
+
+    let monitor = match window.monitor()? {
+        Some(m) => Some(m),
+        None => window.primary()?,
+    };
+    // an excluded current monitor falls back to the first allowed one
+    Ok(monitor
+        .filter(|c| monitors.iter().any(|m| m.name == c.name))
+        .or_else(|| monitors.into_iter().next()))
+}
+
+/// The name of the excluded monitor `state` is on and the position centering it
+/// on an allowed monitor instead, see [`Builder::with_excluded_monitors`].
+fn excluded_fallback(
+    window: &dyn WindowOps,
+    state: &WindowState,
+    size: Option<LogicalSize<f64>>,
+) -> tauri::Result<Option<(String, MonitorInfo, PhysicalPosition<i32>)>> {
+    let position = (state.x, state.y).into();
+    let stored = window
+        .excluded_monitors()?
+        .into_iter()
+        .find(|m| m.intersects(position, (state.width, state.height).into()))
+        .and_then(|m| m.name);
+    let stored = match stored {
+        Some(stored) => stored,
+        None => return Ok(None),
+    };
+
+    let monitors = window.monitors()?;
+    let primary = window
+        .primary()?
+        .filter(|p| monitors.iter().any(|m| m.name == p.name));
+    let target = match primary.or_else(|| monitors.into_iter().next()) {
+        Some(monitor) => monitor,
+        None => return Ok(None),
+    };
+    let size = size
+        .unwrap_or(LogicalSize {
+            width: state.width,
+            height: state.height,
+        })
+        .to_physical::<u32>(target.scale_factor);
+    let position = PhysicalPosition {
+        x: target.x + (target.width as i32 - size.width as i32) / 2,
+        y: target.y + (target.height as i32 - size.height as i32) / 2,
+    };
+    Ok(Some((stored, target, position)))
+}
+
+/// Moves and resizes `window` to cover `snap` of the monitor it is on.
+fn apply_snap(window: &dyn WindowOps, snap: SnapPosition) -> tauri::Result<()> {
+    let monitor = match window.monitor()? {
+        Some(monitor) => monitor,
+        None => return Ok(()),
+    };
+    let target = snap.rect(validation::Rect::new(
+        (monitor.x, monitor.y).into(),
+        (monitor.width, monitor.height).into(),
+    ));
+    // the bounds include the decorations, but the size is set without them
+    let decorations = window.decorations_size()?;
+    window.set_physical_size(PhysicalSize::new(
+        target.width.saturating_sub(decorations.width),
+        target.height.saturating_sub(decorations.height),
+    ))?;
+    window.set_physical_position(PhysicalPosition::new(target.x, target.y))
+}
+
+/// Gives `window` its current size, or [`FALLBACK_NORMAL_SIZE`] if it has none,
+/// and centers it, for maximized windows stored without a usable normal size.
+fn substitute_normal_size(
+    window: &dyn WindowOps,
+    label: &str,
+    warn: &dyn Fn(Warning),
+) -> tauri::Result<()> {
+    let current = window.logical_size()?;
+    let size = match current.width > 0. && current.height > 0. {
+        true => current,
+        false => FALLBACK_NORMAL_SIZE,
+    };
+    window.set_logical_size(size)?;
+    window.center()?;
+    warn(Warning::InvalidNormalSize {
+        label: label.into(),
+        substituted: size,
+    });
+    Ok(())
+}
+
+/// Moves `window` onto the connected monitor named `name`, unless it already is on it,
+/// so maximizing fills that monitor.
+fn move_to_monitor(window: &dyn WindowOps, name: &str) -> tauri::Result<()> {
+    let on_monitor = window
+        .monitor()?
+        .map_or(false, |m| m.name.as_deref() == Some(name));
+    if on_monitor {
+        return Ok(());
+    }
+    let monitor = window
+        .monitors()?
+        .into_iter()
+        .find(|m| m.name.as_deref() == Some(name));
+    match monitor {
+        Some(monitor) => window.set_physical_position(PhysicalPosition::new(monitor.x, monitor.y)),
+        None => Ok(()),
+    }
+}
+
+/// Clamps the normal size of `window` to the monitor it is on, `size` being the restored
+/// size if any, otherwise the current one.
+fn fit_normal_size(
+    window: &dyn WindowOps,
+    label: &str,
+    size: Option<LogicalSize<f64>>,
+    warn: &dyn Fn(Warning),
+) -> tauri::Result<()> {
+    let monitor = match window.monitor()? {
+        Some(monitor) => monitor,
+        None => return Ok(()),
+    };
+    let requested = match size {
+        Some(size) => size,
+        None => window.logical_size()?,
+    };
+    if monitor.fits(requested) {
+        return Ok(());
+    }
+
+    let bounds =
+        PhysicalSize::new(monitor.width, monitor.height).to_logical::<f64>(monitor.scale_factor);
+    let clamped = LogicalSize {
+        width: requested.width.min(bounds.width),
+        height: requested.height.min(bounds.height),
+    };
+    window.set_logical_size(clamped)?;
+    warn(Warning::OversizeRestore {
+        label: label.into(),
+        monitor: monitor.name,
+        requested,
+        clamped,
+    });
+    Ok(())
+}
+
+trait MonitorExt {
+    fn intersects(&self, position: PhysicalPosition<i32>, size: LogicalSize<u32>) -> bool;
+    fn fits(&self, size: LogicalSize<f64>) -> bool;
+}
+
+impl MonitorExt for MonitorInfo {
+    fn fits(&self, size: LogicalSize<f64>) -> bool {
+        let bounds =
+            PhysicalSize::new(self.width, self.height).to_logical::<f64>(self.scale_factor);
+        size.width <= bounds.width && size.height <= bounds.height
+    }
+
+    fn intersects(&self, position: PhysicalPosition<i32>, size: LogicalSize<u32>) -> bool {
+        let size = size.to_physical::<u32>(self.scale_factor);
+        validation::has_corner_in(
+            validation::Rect::new(position, size),
+            validation::Rect::new((self.x, self.y).into(), (self.width, self.height).into()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockWindow;
+
+    use std::cell::RefCell;
+
+    /// A window state at `x` and `y` with the logical size `width` by `height`.
+    fn placed(x: i32, y: i32, width: f64, height: f64) -> WindowState {
+        WindowState {
+            x,
+            y,
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    /// The states of windows 600 high with the given labels and widths.
+    pub(crate) fn layout(windows: &[(&str, f64)]) -> HashMap<String, WindowState> {
+        windows
+            .iter()
+            .map(|(label, width)| (label.to_string(), placed(0, 0, *width, 600.)))
+            .collect()
+    }
+
+    /// A flat map of the given window states, as loaded from a state file.
+    fn persisted(windows: Vec<(&str, WindowState)>) -> PersistedState {
+        PersistedState::from_windows(
+            windows
+                .into_iter()
+                .map(|(label, state)| (label.to_string(), state))
+                .collect(),
+        )
+    }
+
+    /// The state file of the first release of the plugin: `bincode::serialize` of a
+    /// `HashMap<String, WindowState>` holding an 800x600 window `main` at 100,50,
+    /// from before `WindowState` had more than its ten geometry fields.
+    const BASELINE_STATE: [u8; 56] = [
+        1, 0, 0, 0, 0, 0, 0, 0, // one entry
+        4, 0, 0, 0, 0, 0, 0, 0, b'm', b'a', b'i', b'n', // the label
+        0, 0, 0, 0, 0, 0, 137, 64, // width
+        0, 0, 0, 0, 0, 192, 130, 64, // height
+        100, 0, 0, 0, 50, 0, 0, 0, // x and y
+        100, 0, 0, 0, 50, 0, 0, 0, // prev_x and prev_y
+        0, 1, 1, 0, // maximized, visible, decorated and fullscreen
+    ];
+
+    /// The window stored in [`BASELINE_STATE`].
+    fn baseline_window() -> WindowState {
+        WindowState {
+            prev_x: 100,
+            prev_y: 50,
+            ..placed(100, 50, 800., 600.)
+        }
+    }
+
+    /// A monitor at `x` on the top edge with a scale factor of 1.
+    fn monitor(name: &str, x: i32, width: u32, height: u32) -> MonitorInfo {
+        MonitorInfo {
+            name: Some(name.into()),
+            x,
+            y: 0,
+            width,
+            height,
+            scale_factor: 1.,
+        }
+    }
+
+    /// Plans and applies restoring `state` on `window`, returning the plan and the warnings.
+    fn restore_on(
+        window: &MockWindow,
+        mut state: WindowState,
+        flags: StateFlags,
+        options: RestoreOptions,
+    ) -> tauri::Result<(RestorePlan, Vec<Warning>)> {
+        let warnings = RefCell::new(Vec::new());
+        let mut plan = plan_geometry(window, &mut state, flags, options, None)?;
+        let warn = |warning: Warning| warnings.borrow_mut().push(warning);
+        apply_plan(window, "main", &state, &mut plan, flags, options, &warn)?;
+        Ok((plan, warnings.into_inner()))
+    }
+
+    /// Restores `state` on the hidden `window` and shows it, like a restore with
+    /// [`REAPPLY_GEOMETRY_AFTER_SHOW`] set does.
+    fn restore_hidden(window: &MockWindow, mut state: WindowState, flags: StateFlags) {
+        let options = RestoreOptions::default();
+        let mut plan = plan_geometry(window, &mut state, flags, options, None).unwrap();
+        let ignore = |_: Warning| {};
+        let geometry =
+            apply_plan(window, "main", &state, &mut plan, flags, options, &ignore).unwrap();
+        show_and_reapply(window, geometry).unwrap();
+        assert!(window.visible());
+    }
+
+    #[test]
+    fn sanitize_rejects_malformed_sizes() {
+        let mut state = persisted(vec![
+            ("nan", placed(0, 0, f64::NAN, 600.)),
+            ("infinite", placed(0, 0, 800., f64::INFINITY)),
+            ("negative", placed(0, 0, -1., 600.)),
+            ("valid", placed(0, 0, 800., 600.)),
+        ]);
+        let warnings = sanitize_windows(&mut state);
+
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings
+            .iter()
+            .all(|w| matches!(w, Warning::RejectedState { label, .. } if label != "valid")));
+        assert_eq!(state.windows.keys().collect::<Vec<_>>(), vec!["valid"]);
+    }
+
+    #[test]
+    fn sanitize_clamps_oversized_geometry() {
+        let mut state = persisted(vec![(
+            "main",
+            WindowState {
+                x: i32::MAX,
+                y: i32::MIN,
+                prev_x: -2 * MAX_COORDINATE,
+                ..placed(0, 0, 1e12, 1e9)
+            },
+        )]);
+        assert!(sanitize_windows(&mut state).is_empty());
+
+        let window = &state.windows["main"];
+        assert_eq!(
+            (window.width, window.height),
+            (MAX_WINDOW_SIZE, MAX_WINDOW_SIZE)
+        );
+        assert_eq!((window.x, window.y), (MAX_COORDINATE, -MAX_COORDINATE));
+        assert_eq!(window.prev_x, -MAX_COORDINATE);
+    }
+
+    #[test]
+    fn sanitize_rejects_states_beyond_the_limit() {
+        let mut state = PersistedState::from_windows(
+            (0..MAX_WINDOW_STATES + 5)
+                .map(|i| (format!("window-{:05}", i), WindowState::default()))
+                .collect(),
+        );
+        let warnings = sanitize_windows(&mut state);
+
+        assert_eq!(warnings.len(), 5);
+        assert_eq!(state.windows.len(), MAX_WINDOW_STATES);
+        // the labels are sorted, so the same states are kept on every load
+        assert!(state.windows.contains_key("window-00000"));
+        assert!(!state
+            .windows
+            .contains_key(&format!("window-{:05}", MAX_WINDOW_STATES)));
+    }
+
+    #[test]
+    fn sanitize_drops_malformed_optional_fields() {
+        let monitor = MonitorInfo {
+            name: Some("primary".into()),
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 1080,
+            scale_factor: 1.,
+        };
+        let mut state = persisted(vec![
+            (
+                "nan",
+                WindowState {
+                    monitor: Some(monitor),
+                    fractions: Some(GeometryFractions {
+                        x: 0.,
+                        y: f64::NAN,
+                        width: 0.5,
+                        height: 0.5,
+                    }),
+                    zoom: Some(f64::INFINITY),
+                    ..Default::default()
+                },
+            ),
+            (
+                "negative-zoom",
+                WindowState {
+                    zoom: Some(-1.),
+                    ..Default::default()
+                },
+            ),
+            (
+                "valid",
+                WindowState {
+                    fractions: Some(GeometryFractions {
+                        x: 0.25,
+                        y: 0.25,
+                        width: 0.5,
+                        height: 0.5,
+                    }),
+                    zoom: Some(1.5),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        assert!(sanitize_windows(&mut state).is_empty());
+
+        let nan = &state.windows["nan"];
+        assert_eq!((&nan.monitor, nan.fractions, nan.zoom), (&None, None, None));
+        assert_eq!(state.windows["negative-zoom"].zoom, None);
+        assert!(state.windows["valid"].fractions.is_some());
+        assert_eq!(state.windows["valid"].zoom, Some(1.5));
+    }
+
+    #[test]
+    fn sanitize_checks_slot_entries() {
+        let mut state = persisted(vec![]);
+        state.metadata.slots.insert(
+            1,
+            [
+                ("nan".to_string(), placed(0, 0, f64::NAN, 600.)),
+                ("huge".to_string(), placed(0, 0, 1e12, 600.)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        state.metadata.slots.insert(
+            MAX_SLOTS,
+            [("main".to_string(), placed(0, 0, 800., 600.))].into(),
+        );
+        let warnings = sanitize_windows(&mut state);
+
+        assert!(matches!(
+            warnings.as_slice(),
+            [Warning::RejectedState { label, reason }] if label == "nan" && reason.ends_with("in slot 1")
+        ));
+        assert_eq!(state.metadata.slots.keys().collect::<Vec<_>>(), vec![&1]);
+        let slot = &state.metadata.slots[&1];
+        assert!(!slot.contains_key("nan"));
+        assert_eq!(slot["huge"].width, MAX_WINDOW_SIZE);
+    }
+
+    #[test]
+    fn annotated_json_round_trips() {
+        let state: HashMap<String, WindowState> = [(
+            "main".to_string(),
+            WindowState {
+                x: 100,
+                y: 50,
+                monitor: Some(MonitorInfo {
+                    name: Some("DELL U2720Q".into()),
+                    x: 0,
+                    y: 0,
+                    width: 2560,
+                    height: 1440,
+                    scale_factor: 1.,
+                }),
+                user_data: Some(serde_json::json!({ "tab": 2 })),
+                ..placed(0, 0, 800., 600.)
+            },
+        )]
+        .into();
+        let metadata = PersistedMetadata::default();
+        let bytes = encode_persisted(&state, &metadata, StateFormat::Json, true).unwrap();
+
+        let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(value["_comment"].is_string());
+        assert_eq!(
+            value["windows"]["main"]["_note"],
+            "800x600 at 100,50 on monitor DELL U2720Q"
+        );
+
+        let loaded = deserialize_state(&bytes).unwrap();
+        assert_eq!(loaded.version, STATE_VERSION);
+        assert_eq!(loaded.windows, state);
+        // written again, the notes are replaced rather than accumulated
+        let rewritten =
+            encode_persisted(&loaded.windows, &loaded.metadata, StateFormat::Json, true);
+        assert_eq!(rewritten.unwrap(), bytes);
+    }
+
+    #[test]
+    fn annotations_only_apply_to_json() {
+        let state: HashMap<String, WindowState> =
+            [("main".to_string(), placed(0, 0, 800., 600.))].into();
+        let metadata = PersistedMetadata::default();
+        let plain = encode_persisted(&state, &metadata, StateFormat::Json, false).unwrap();
+        assert!(!String::from_utf8(plain).unwrap().contains("_note"));
+        assert_eq!(
+            encode_persisted(&state, &metadata, StateFormat::Bincode, true).unwrap(),
+            encode_persisted(&state, &metadata, StateFormat::Bincode, false).unwrap()
+        );
+    }
+
+    #[test]
+    fn rename_moves_the_state() {
+        let mut state = layout(&[("old", 800.)]);
+        rename_entry(&mut state, "old", "new", false).unwrap();
+        assert_eq!(state, layout(&[("new", 800.)]));
+    }
+
+    #[test]
+    fn rename_fails_without_a_source() {
+        let mut state = layout(&[("other", 800.)]);
+        assert!(matches!(
+            rename_entry(&mut state, "old", "new", true),
+            Err(Error::StateNotFound(label)) if label == "old"
+        ));
+        assert_eq!(state, layout(&[("other", 800.)]));
+    }
+
+    #[test]
+    fn rename_conflicts_unless_overwriting() {
+        let mut state = layout(&[("old", 800.), ("new", 1024.)]);
+        assert!(matches!(
+            rename_entry(&mut state, "old", "new", false),
+            Err(Error::StateAlreadyExists(label)) if label == "new"
+        ));
+        assert_eq!(state, layout(&[("old", 800.), ("new", 1024.)]));
+
+        rename_entry(&mut state, "old", "new", true).unwrap();
+        assert_eq!(state, layout(&[("new", 800.)]));
+    }
+
+    #[test]
+    fn rename_to_the_same_label_is_a_no_op() {
+        let mut state = layout(&[("main", 800.)]);
+        rename_entry(&mut state, "main", "main", false).unwrap();
+        assert_eq!(state, layout(&[("main", 800.)]));
+        assert!(matches!(
+            rename_entry(&mut state, "missing", "missing", false),
+            Err(Error::StateNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn encoding_is_deterministic() {
+        let window = |i: usize| (format!("window-{}", i), placed(0, 0, 100. + i as f64, 600.));
+        // the same states inserted in opposite orders, into differently seeded maps
+        let forward: HashMap<String, WindowState> = (0..32).map(window).collect();
+        let mut backward = HashMap::new();
+        for i in (0..32).rev() {
+            let (label, state) = window(i);
+            backward.insert(label, state);
+        }
+        assert_eq!(forward, backward);
+
+        let metadata = |windows: &HashMap<String, WindowState>| PersistedMetadata {
+            slots: [(0, windows.clone()), (1, windows.clone())].into(),
+            ..Default::default()
+        };
+        for format in [StateFormat::Bincode, StateFormat::Json] {
+            let a = encode_persisted(&forward, &metadata(&forward), format, true).unwrap();
+            let b = encode_persisted(&backward, &metadata(&backward), format, true).unwrap();
+            assert_eq!(a, b);
+            assert_eq!(
+                encode_persisted(&forward, &metadata(&forward), format, true).unwrap(),
+                a
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_reads_the_baseline_format() {
+        let state = deserialize_state(&BASELINE_STATE).unwrap();
+        assert_eq!(state.version, 0);
+        assert_eq!(
+            state.windows,
+            persisted(vec![("main", baseline_window())]).windows
+        );
+        assert!(state.metadata.slots.is_empty());
+    }
+
+    #[test]
+    fn deserialize_reads_a_flat_map() {
+        let windows = persisted(vec![
+            ("main", baseline_window()),
+            (
+                "settings",
+                WindowState {
+                    theme: Some(Theme::Dark),
+                    zoom: Some(1.25),
+                    ..placed(0, 0, 400., 300.)
+                },
+            ),
+        ])
+        .windows;
+        let bytes = bincode::serialize(&windows).unwrap();
+        let state = deserialize_state(&bytes).unwrap();
+        assert_eq!(state.version, 0);
+        assert_eq!(state.windows, windows);
+
+        let json = serde_json::to_vec(&windows).unwrap();
+        assert_eq!(deserialize_state(&json).unwrap().windows, windows);
+    }
+
+    #[test]
+    fn deserialize_round_trips_the_envelope() {
+        let windows = persisted(vec![("main", baseline_window())]).windows;
+        let metadata = PersistedMetadata {
+            focused_label: Some("main".into()),
+            slots: [(3, windows.clone())].into(),
+            clean_exit: Some(false),
+            ..Default::default()
+        };
+        for format in [StateFormat::Bincode, StateFormat::Json] {
+            let bytes = encode_persisted(&windows, &metadata, format, false).unwrap();
+            let state = deserialize_state(&bytes).unwrap();
+            assert_eq!(state.version, STATE_VERSION);
+            assert_eq!(state.windows, windows);
+            assert_eq!(state.metadata.focused_label.as_deref(), Some("main"));
+            assert_eq!(state.metadata.slots[&3], windows);
+            assert_eq!(state.metadata.clean_exit, Some(false));
+        }
+    }
+
+    #[test]
+    fn deserialize_rejects_trailing_garbage() {
+        let windows = persisted(vec![("main", baseline_window())]).windows;
+        let envelope =
+            encode_persisted(&windows, &Default::default(), StateFormat::Bincode, false).unwrap();
+        for bytes in [envelope, BASELINE_STATE.to_vec()] {
+            let mut garbage = bytes.clone();
+            garbage.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+            assert!(deserialize_state(&bytes).is_ok());
+            assert!(deserialize_state(&garbage).is_err());
+        }
+
+        assert_eq!(bincode_exact::<u32>(&[7, 0, 0, 0]).unwrap(), 7);
+        assert!(bincode_exact::<u32>(&[7, 0, 0, 0, 0]).is_err());
+        assert!(bincode_exact::<u32>(&[7, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn minimized_windows_keep_their_geometry() {
+        let mut state = WindowState {
+            monitor: Some(monitor("primary", 0, 1920, 1080)),
+            ..baseline_window()
+        };
+        // some platforms report a zero size and an off-screen position for minimized windows
+        let properties = WindowProperties {
+            minimized: true,
+            visible: true,
+            decorated: false,
+            position: PhysicalPosition::new(-32000, -32000),
+            ..Default::default()
+        };
+        merge_properties(&mut state, &properties, StateFlags::all(), false);
+
+        assert!(state.minimized);
+        assert!(state.visible);
+        assert!(!state.decorated);
+        assert_eq!((state.width, state.height), (800., 600.));
+        assert_eq!((state.x, state.y), (100, 50));
+        assert_eq!(state.monitor, Some(monitor("primary", 0, 1920, 1080)));
+    }
+
+    #[test]
+    fn zero_sizes_are_ignored_without_blocking_other_fields() {
+        let mut state = baseline_window();
+        let properties = WindowProperties {
+            visible: true,
+            decorated: true,
+            position: PhysicalPosition::new(300, 200),
+            monitor: Some(monitor("primary", 0, 1920, 1080)),
+            fullscreen: true,
+            ..Default::default()
+        };
+        merge_properties(&mut state, &properties, StateFlags::all(), false);
+
+        assert_eq!((state.width, state.height), (800., 600.));
+        assert_eq!((state.x, state.y), (300, 200));
+        assert_eq!(state.fullscreen, FullscreenMode::Borderless);
+        assert!(state.monitor.is_some());
+    }
+
+    #[test]
+    fn unminimized_windows_clear_the_flag() {
+        let mut state = WindowState {
+            minimized: true,
+            ..baseline_window()
+        };
+        let properties = WindowProperties {
+            visible: true,
+            size: LogicalSize::new(1024., 768.),
+            position: PhysicalPosition::new(10, 20),
+            ..Default::default()
+        };
+        merge_properties(&mut state, &properties, StateFlags::all(), false);
+        assert!(!state.minimized);
+        assert_eq!((state.width, state.height), (1024., 768.));
+
+        // the flag is tracked along with the visibility
+        state.minimized = true;
+        merge_properties(&mut state, &properties, StateFlags::SIZE, false);
+        assert!(state.minimized);
+    }
+
+    #[test]
+    fn deserialize_rejects_oversized_input() {
+        let bytes = vec![b' '; MAX_STATE_BYTES + 1];
+        assert!(matches!(
+            deserialize_state(&bytes),
+            Err(Error::StateTooLarge(len)) if len == MAX_STATE_BYTES + 1
+        ));
+    }
+
+    #[test]
+    fn restore_applies_the_stored_geometry() {
+        let window = MockWindow::new(vec![monitor("primary", 0, 1920, 1080)]);
+        let flags = StateFlags::SIZE | StateFlags::POSITION;
+        let (plan, warnings) = restore_on(
+            &window,
+            placed(100, 50, 800., 600.),
+            flags,
+            RestoreOptions::default(),
+        )
+        .unwrap();
+
+        assert!(plan.adjustments.is_empty());
+        assert!(warnings.is_empty());
+        assert_eq!(window.size(), LogicalSize::new(800., 600.));
+        assert_eq!(window.position(), PhysicalPosition::new(100, 50));
+        assert_eq!(
+            window.calls(),
+            vec!["set_size 800x600", "set_position 100,50"]
+        );
+    }
+
+    #[test]
+    fn restore_maximizes_over_the_normal_geometry() {
+        let window = MockWindow::new(vec![monitor("primary", 0, 1920, 1080)]);
+        let state = WindowState {
+            maximized: true,
+            prev_x: 200,
+            prev_y: 100,
+            ..placed(0, 0, 800., 600.)
+        };
+        let flags = StateFlags::SIZE | StateFlags::POSITION | StateFlags::MAXIMIZED;
+        let (plan, _) = restore_on(&window, state, flags, RestoreOptions::default()).unwrap();
+
+        assert!(plan.maximize);
+        assert!(window.maximized());
+        // the normal geometry is applied first, so un-maximizing returns to it
+        assert_eq!(
+            window.calls(),
+            vec!["set_size 800x600", "set_position 200,100", "maximize"]
+        );
+    }
+
+    #[test]
+    fn offscreen_positions_are_left_to_the_os() {
+        let window = MockWindow::new(vec![monitor("primary", 0, 1920, 1080)]);
+        let flags = StateFlags::SIZE | StateFlags::POSITION;
+        let (plan, _) = restore_on(
+            &window,
+            placed(5000, 5000, 800., 600.),
+            flags,
+            RestoreOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(plan.position, None);
+        assert_eq!(plan.adjustments, vec![Adjustment::OffscreenDiscarded]);
+        assert_eq!(window.position(), PhysicalPosition::new(0, 0));
+        assert_eq!(window.calls(), vec!["set_size 800x600"]);
+    }
+
+    #[test]
+    fn maximized_windows_move_to_their_monitor_first() {
+        let window = MockWindow::new(vec![
+            monitor("primary", 0, 1920, 1080),
+            monitor("right", 1920, 2560, 1440),
+        ]);
+        let state = WindowState {
+            maximized: true,
+            maximized_monitor: Some("right".into()),
+            prev_x: 100,
+            prev_y: 100,
+            ..placed(0, 0, 800., 600.)
+        };
+        let flags = StateFlags::SIZE | StateFlags::POSITION | StateFlags::MAXIMIZED;
+        restore_on(&window, state, flags, RestoreOptions::default()).unwrap();
+
+        assert_eq!(
+            window.calls(),
+            vec![
+                "set_size 800x600",
+                "set_position 100,100",
+                "set_position 1920,0",
+                "maximize"
+            ]
+        );
+        assert_eq!(
+            window.monitor().unwrap().and_then(|m| m.name),
+            Some("right".into())
+        );
+    }
+
+    #[test]
+    fn missing_monitors_record_the_fallback() {
+        let window = MockWindow::new(vec![monitor("primary", 0, 1920, 1080)]);
+        let state = WindowState {
+            monitor: Some(monitor("gone", 1920, 1920, 1080)),
+            ..placed(2020, 100, 800., 600.)
+        };
+        let options = RestoreOptions {
+            proportional_remap: true,
+            ..Default::default()
+        };
+        let flags = StateFlags::SIZE | StateFlags::POSITION;
+        let (plan, _) = restore_on(&window, state, flags, options).unwrap();
+
+        assert_eq!(
+            plan.adjustments,
+            vec![Adjustment::MonitorMismatchFallback {
+                stored: "gone".into(),
+                used: Some("primary".into()),
+            }]
+        );
+        assert_eq!(
+            window.monitor().unwrap().and_then(|m| m.name),
+            Some("primary".into())
+        );
+    }
+
+    #[test]
+    fn oversized_windows_record_the_reduction() {
+        let window = MockWindow::new(vec![monitor("small", 0, 1280, 720)]);
+        let (plan, _) = restore_on(
+            &window,
+            placed(0, 0, 1920., 1080.),
+            StateFlags::SIZE,
+            RestoreOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan.adjustments,
+            vec![Adjustment::OversizeReduced {
+                monitor: Some("small".into()),
+                requested: LogicalSize::new(1920., 1080.),
+                clamped: LogicalSize::new(1280., 720.),
+            }]
+        );
+        assert_eq!(window.size(), LogicalSize::new(1280., 720.));
+    }
+
+    #[test]
+    fn snapped_windows_record_the_snap() {
+        let window = MockWindow::new(vec![monitor("primary", 0, 1920, 1080)]);
+        let state = WindowState {
+            snap: Some(SnapPosition::Right),
+            ..placed(900, 0, 960., 1000.)
+        };
+        let flags = StateFlags::SIZE | StateFlags::POSITION | StateFlags::SNAP;
+        let (plan, _) = restore_on(&window, state, flags, RestoreOptions::default()).unwrap();
+
+        assert_eq!(
+            plan.adjustments,
+            vec![Adjustment::Snapped {
+                snap: SnapPosition::Right
+            }]
+        );
+        // the snap applies after the stored geometry and wins over it
+        assert_eq!(window.size(), LogicalSize::new(960., 1080.));
+        assert_eq!(window.position(), PhysicalPosition::new(960, 0));
+    }
+
+    #[test]
+    fn tiny_monitors_clamp_the_normal_size_of_maximized_windows() {
+        let window = MockWindow::new(vec![monitor("tiny", 0, 640, 480)]);
+        let state = WindowState {
+            maximized: true,
+            ..placed(0, 0, 1920., 1080.)
+        };
+        let flags = StateFlags::SIZE | StateFlags::POSITION | StateFlags::MAXIMIZED;
+        let options = RestoreOptions {
+            fit_before_maximize: true,
+            ..Default::default()
+        };
+        restore_on(&window, state, flags, options).unwrap();
+
+        assert_eq!(
+            window.calls(),
+            vec!["set_size 640x480", "set_position 0,0", "maximize"]
+        );
+    }
+
+    #[test]
+    fn fit_before_maximize_clamps_the_current_size() {
+        let window = MockWindow::new(vec![monitor("tiny", 0, 640, 480)])
+            .with_size(LogicalSize::new(1024., 768.));
+        let state = WindowState {
+            maximized: true,
+            ..placed(0, 0, 1920., 1080.)
+        };
+        let flags = StateFlags::POSITION | StateFlags::MAXIMIZED;
+        let options = RestoreOptions {
+            fit_before_maximize: true,
+            ..Default::default()
+        };
+        let (_, warnings) = restore_on(&window, state, flags, options).unwrap();
+
+        assert_eq!(
+            window.calls(),
+            vec!["set_position 0,0", "set_size 640x480", "maximize"]
+        );
+        assert!(matches!(
+            &warnings[..],
+            [Warning::OversizeRestore { clamped, .. }] if *clamped == LogicalSize::new(640., 480.)
+        ));
+    }
+
+    #[test]
+    fn zero_normal_sizes_fall_back_before_maximizing() {
+        let window = MockWindow::new(vec![monitor("primary", 0, 1920, 1080)])
+            .with_size(LogicalSize::new(0., 0.));
+        let state = WindowState {
+            maximized: true,
+            ..placed(0, 0, 0., 0.)
+        };
+        let flags = StateFlags::SIZE | StateFlags::POSITION | StateFlags::MAXIMIZED;
+        let (plan, warnings) =
+            restore_on(&window, state, flags, RestoreOptions::default()).unwrap();
+
+        assert_eq!((plan.size, plan.position), (None, None));
+        assert_eq!(
+            window.calls(),
+            vec!["set_size 800x600", "center", "maximize"]
+        );
+        assert_eq!(window.position(), PhysicalPosition::new(560, 240));
+        assert!(matches!(
+            &warnings[..],
+            [Warning::InvalidNormalSize { substituted, .. }] if *substituted == FALLBACK_NORMAL_SIZE
+        ));
+    }
+
+    #[test]
+    fn hidden_windows_get_their_geometry_again_once_shown() {
+        let window = MockWindow::new(vec![monitor("primary", 0, 1920, 1080)]).hidden();
+        let flags = StateFlags::SIZE | StateFlags::POSITION;
+        restore_hidden(&window, placed(100, 50, 800., 600.), flags);
+
+        assert_eq!(
+            window.calls(),
+            vec![
+                "set_size 800x600",
+                "set_position 100,50",
+                "show",
+                "set_focus",
+                "set_size 800x600",
+                "set_position 100,50"
+            ]
+        );
+    }
+
+    #[test]
+    fn hidden_maximized_windows_are_only_shown() {
+        let window = MockWindow::new(vec![monitor("primary", 0, 1920, 1080)]).hidden();
+        let state = WindowState {
+            maximized: true,
+            ..placed(100, 50, 800., 600.)
+        };
+        let flags = StateFlags::SIZE | StateFlags::POSITION | StateFlags::MAXIMIZED;
+        restore_hidden(&window, state, flags);
+
+        // applying the normal geometry again would un-maximize the window on some platforms
+        assert_eq!(
+            window.calls(),
+            vec![
+                "set_size 800x600",
+                "set_position 0,0",
+                "maximize",
+                "show",
+                "set_focus"
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_restores_roll_the_window_back() {
+        let window = MockWindow::new(vec![monitor("primary", 0, 1920, 1080)]).failing("maximize");
+        let cache = HashMap::from([(
+            "main".to_string(),
+            WindowState {
+                maximized: true,
+                ..placed(100, 50, 800., 600.)
+            },
+        )]);
+        let before = cache.clone();
+        let flags = StateFlags::SIZE | StateFlags::POSITION | StateFlags::MAXIMIZED;
+
+        // restores work on a copy of the stored state
+        let result = with_rollback(&window, || {
+            restore_on(
+                &window,
+                cache["main"].clone(),
+                flags,
+                RestoreOptions::default(),
+            )
+        });
+        assert!(result.is_err());
+        assert_eq!(cache, before);
+        assert_eq!(window.size(), LogicalSize::new(640., 480.));
+        assert_eq!(window.position(), PhysicalPosition::new(0, 0));
+        assert!(!window.maximized());
+        assert!(!window.fullscreen());
+        assert!(window.decorated());
+    }
+
+    #[test]
+    fn failed_setters_are_skipped_when_continuing_on_errors() {
+        let window = MockWindow::new(vec![monitor("primary", 0, 1920, 1080)]).failing("set_size");
+        let options = RestoreOptions {
+            continue_on_error: true,
+            ..Default::default()
+        };
+        let flags = StateFlags::SIZE | StateFlags::POSITION;
+        let (_, warnings) =
+            restore_on(&window, placed(100, 50, 800., 600.), flags, options).unwrap();
+
+        assert_eq!(window.position(), PhysicalPosition::new(100, 50));
+        assert!(matches!(
+            &warnings[..],
+            [Warning::RestoreSetterFailed {
+                setter: "set_size",
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn duplicate_labels_are_detected_while_open() {
+        let live = LiveWindows::default();
+        assert!(!live.open("main"));
+        assert!(live.open("main"));
+        assert!(!live.open("settings"));
+
+        // the label stays taken until every window with it is closed
+        live.close("main");
+        assert!(live.open("main"));
+        live.close("main");
+        live.close("main");
+        assert!(!live.open("main"));
+    }
+
+    #[test]
+    fn save_deadlines_of_each_kind_are_kept_apart() {
+        let (tx, rx) = channel();
+        let start = Instant::now();
+        let worker = std::thread::spawn(move || {
+            let mut saves = Vec::new();
+            run_save_worker(rx, || saves.push(start.elapsed()), |_| {});
+            saves
+        });
+        let moved =
+            SaveMessage::Changed("main".into(), ChangeKind::Moved, Duration::from_millis(10));
+        let resized = SaveMessage::Changed(
+            "main".into(),
+            ChangeKind::Resized,
+            Duration::from_millis(300),
+        );
+        tx.send(moved).unwrap();
+        tx.send(resized).unwrap();
+        std::thread::sleep(Duration::from_millis(600));
+        tx.send(SaveMessage::Stop).unwrap();
+
+        // flushing the move doesn't cancel the pending resize
+        let saves = worker.join().unwrap();
+        assert_eq!(saves.len(), 2);
+        assert!(saves[0] < Duration::from_millis(300));
+        assert!(saves[1] >= Duration::from_millis(300));
     }
 }
@@ -0,0 +1,210 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use crate::{Error, Result};
+
+use std::{
+    fs::{create_dir_all, rename, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// A place to persist the serialized window state, set with [`crate::Builder::with_store`].
+///
+/// The plugin takes care of serialization and only hands raw bytes to the store,
+/// so implementations can back the state by a database, a shared file or anything else.
+pub trait StateStore: Send + Sync + 'static {
+    /// Loads the previously saved state, or `None` if nothing was saved yet.
+    fn load(&self) -> Result<Option<Vec<u8>>>;
+    /// Saves `bytes`, replacing the previously saved state.
+    fn save(&self, bytes: &[u8]) -> Result<()>;
+    /// The file the state is stored in, if the store is backed by a single file.
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// Stores the state in a single file, the default store.
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl StateStore for FileStore {
+    fn load(&self) -> Result<Option<Vec<u8>>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+
+        tauri::api::file::read_binary(&self.path)
+            .map(Some)
+            .map_err(Error::TauriApi)
+    }
+
+    fn save(&self, bytes: &[u8]) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+
+        // write next to the state file and swap it in, so a crash mid-write
+        // never leaves a truncated state file behind
+        let tmp = tmp_path(&self.path);
+        let mut file = File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        rename(&tmp, &self.path).map_err(Into::into)
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+}
+
+/// The file next to `path` a new state is written to before it replaces the file at `path`.
+pub(crate) fn tmp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.tmp", name))
+}
+
+/// Stores the state where the platform conventionally keeps app preferences,
+/// so it follows OS conventions and is included in OS backups.
+///
+/// - macOS: the `WindowState` key of the user defaults of the app with the bundle identifier `app_id`.
+/// - Windows: the `WindowState` binary value of the `HKEY_CURRENT_USER\Software\{app_id}` registry key.
+/// - Other platforms: the file at `fallback_path`, like a [`FileStore`].
+///
+/// Requires the `native-store` feature.
+#[cfg(feature = "native-store")]
+pub struct NativeStore {
+    #[cfg_attr(not(any(target_os = "macos", windows)), allow(dead_code))]
+    app_id: String,
+    #[cfg_attr(any(target_os = "macos", windows), allow(dead_code))]
+    fallback: FileStore,
+}
+
+#[cfg(feature = "native-store")]
+impl NativeStore {
+    /// The key or value name the state is stored under.
+    #[cfg_attr(not(any(target_os = "macos", windows)), allow(dead_code))]
+    const KEY: &'static str = "WindowState";
+
+    pub fn new<S: Into<String>, P: Into<PathBuf>>(app_id: S, fallback_path: P) -> Self {
+        Self {
+            app_id: app_id.into(),
+            fallback: FileStore::new(fallback_path),
+        }
+    }
+}
+
+#[cfg(all(feature = "native-store", target_os = "macos"))]
+impl StateStore for NativeStore {
+    fn load(&self) -> Result<Option<Vec<u8>>> {
+        use core_foundation::{
+            base::{CFType, TCFType},
+            data::CFData,
+            string::CFString,
+            sys::preferences::CFPreferencesCopyAppValue,
+        };
+
+        let key = CFString::new(Self::KEY);
+        let app_id = CFString::new(&self.app_id);
+        let value = unsafe {
+            CFPreferencesCopyAppValue(key.as_concrete_TypeRef(), app_id.as_concrete_TypeRef())
+        };
+        if value.is_null() {
+            return Ok(None);
+        }
+        let value = unsafe { CFType::wrap_under_create_rule(value) };
+        Ok(value.downcast::<CFData>().map(|data| data.bytes().to_vec()))
+    }
+
+    fn save(&self, bytes: &[u8]) -> Result<()> {
+        use core_foundation::{
+            base::TCFType,
+            data::CFData,
+            string::CFString,
+            sys::preferences::{CFPreferencesAppSynchronize, CFPreferencesSetAppValue},
+        };
+
+        let key = CFString::new(Self::KEY);
+        let app_id = CFString::new(&self.app_id);
+        let data = CFData::from_buffer(bytes);
+        let synchronized = unsafe {
+            CFPreferencesSetAppValue(
+                key.as_concrete_TypeRef(),
+                data.as_CFTypeRef(),
+                app_id.as_concrete_TypeRef(),
+            );
+            CFPreferencesAppSynchronize(app_id.as_concrete_TypeRef())
+        };
+        if synchronized == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "failed to synchronize the user defaults",
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "native-store", windows))]
+impl NativeStore {
+    fn registry_path(&self) -> String {
+        format!("Software\\{}", self.app_id)
+    }
+}
+
+#[cfg(all(feature = "native-store", windows))]
+impl StateStore for NativeStore {
+    fn load(&self) -> Result<Option<Vec<u8>>> {
+        use winreg::{enums::HKEY_CURRENT_USER, RegKey};
+
+        let value = RegKey::predef(HKEY_CURRENT_USER)
+            .open_subkey(self.registry_path())
+            .and_then(|key| key.get_raw_value(Self::KEY));
+        match value {
+            Ok(value) => Ok(Some(value.bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&self, bytes: &[u8]) -> Result<()> {
+        use winreg::{
+            enums::{RegType, HKEY_CURRENT_USER},
+            RegKey, RegValue,
+        };
+
+        let (key, _) = RegKey::predef(HKEY_CURRENT_USER).create_subkey(self.registry_path())?;
+        key.set_raw_value(
+            Self::KEY,
+            &RegValue {
+                bytes: bytes.to_vec(),
+                vtype: RegType::REG_BINARY,
+            },
+        )
+        .map_err(Into::into)
+    }
+}
+
+#[cfg(all(feature = "native-store", not(any(target_os = "macos", windows))))]
+impl StateStore for NativeStore {
+    fn load(&self) -> Result<Option<Vec<u8>>> {
+        self.fallback.load()
+    }
+
+    fn save(&self, bytes: &[u8]) -> Result<()> {
+        self.fallback.save(bytes)
+    }
+
+    fn path(&self) -> Option<&Path> {
+        self.fallback.path()
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Helpers for testing an app's window state integration without a display server,
+//! enabled with the `testing` feature.
+//!
+//! [`MockWindow`] stands in for a window on a given set of monitors, so tests can assert where
+//! [`restore_geometry`] would put a window for a stored state. [`seed_state`] fills the state
+//! of an app running the plugin, for example on Tauri's mock runtime, before restoring windows.
+//!
+//! ```
+//! use tauri::PhysicalPosition;
+//! use tauri_plugin_window_state::{
+//!     testing::{restore_geometry, MockWindow},
+//!     MonitorInfo, StateFlags, WindowState,
+//! };
+//!
+//! # fn main() -> tauri::Result<()> {
+//! let window = MockWindow::new(vec![MonitorInfo {
+//!     name: Some("primary".into()),
+//!     x: 0,
+//!     y: 0,
+//!     width: 1920,
+//!     height: 1080,
+//!     scale_factor: 1.,
+//! }]);
+//! let state = WindowState {
+//!     x: 100,
+//!     y: 100,
+//!     width: 800.,
+//!     height: 600.,
+//!     ..Default::default()
+//! };
+//! restore_geometry(&window, &state, StateFlags::SIZE | StateFlags::POSITION)?;
+//! assert_eq!(window.position(), PhysicalPosition::new(100, 100));
+//! # Ok(())
+//! # }
+//! ```
+
+use tauri::{LogicalSize, Manager, PhysicalPosition, PhysicalSize, Runtime};
+
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{
+    apply_plan, plan_geometry, replace_state, sanitize_state, validation, Geometry, MonitorInfo,
+    PersistedState, RestoreOptions, RestorePlan, StateFlags, Warning, WindowOps, WindowState,
+    WindowStateCache,
+};
+
+/// A window on a fixed set of monitors, recording the geometry and modes applied to it.
+#[derive(Debug)]
+pub struct MockWindow {
+    monitors: Vec<MonitorInfo>,
+    fail: Option<&'static str>,
+    state: Mutex<MockState>,
+}
+
+/// The geometry and modes of a [`MockWindow`], and the operations applied to it.
+#[derive(Debug)]
+struct MockState {
+    size: LogicalSize<f64>,
+    position: PhysicalPosition<i32>,
+    visible: bool,
+    maximized: bool,
+    fullscreen: bool,
+    decorated: bool,
+    calls: Vec<String>,
+}
+
+impl MockWindow {
+    /// A visible, decorated 640x480 window at the origin of the first of `monitors`, if any.
+    pub fn new(monitors: Vec<MonitorInfo>) -> Self {
+        let position = monitors
+            .first()
+            .map_or_else(Default::default, |m| PhysicalPosition::new(m.x, m.y));
+        Self {
+            monitors,
+            fail: None,
+            state: Mutex::new(MockState {
+                size: LogicalSize::new(640., 480.),
+                position,
+                visible: true,
+                maximized: false,
+                fullscreen: false,
+                decorated: true,
+                calls: Vec::new(),
+            }),
+        }
+    }
+
+    /// Moves the window to the origin of the monitor at `index` in the list it was created with.
+    pub fn on_monitor(self, index: usize) -> Self {
+        if let Some(m) = self.monitors.get(index) {
+            self.state.lock().unwrap().position = PhysicalPosition::new(m.x, m.y);
+        }
+        self
+    }
+
+    /// Gives the window the logical inner size `size`.
+    pub fn with_size(self, size: LogicalSize<f64>) -> Self {
+        self.state.lock().unwrap().size = size;
+        self
+    }
+
+    /// Hides the window.
+    pub fn hidden(self) -> Self {
+        self.state.lock().unwrap().visible = false;
+        self
+    }
+
+    /// Makes the operations starting with `call` fail, for example `"maximize"`,
+    /// see [`MockWindow::calls`].
+    pub fn failing(mut self, call: &'static str) -> Self {
+        self.fail = Some(call);
+        self
+    }
+
+    /// The logical inner size of the window.
+    pub fn size(&self) -> LogicalSize<f64> {
+        self.state.lock().unwrap().size
+    }
+
+    /// The physical outer position of the window.
+    pub fn position(&self) -> PhysicalPosition<i32> {
+        self.state.lock().unwrap().position
+    }
+
+    pub fn visible(&self) -> bool {
+        self.state.lock().unwrap().visible
+    }
+
+    pub fn maximized(&self) -> bool {
+        self.state.lock().unwrap().maximized
+    }
+
+    pub fn fullscreen(&self) -> bool {
+        self.state.lock().unwrap().fullscreen
+    }
+
+    pub fn decorated(&self) -> bool {
+        self.state.lock().unwrap().decorated
+    }
+
+    /// The operations applied to the window in order, like `set_size 800x600`,
+    /// `set_position 100,50` or `maximize`, including the ones that failed.
+    pub fn calls(&self) -> Vec<String> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Records `call` and applies `change`, unless the call fails.
+    fn apply(&self, call: String, change: impl FnOnce(&mut MockState)) -> tauri::Result<()> {
+        let failed = self.fail.map_or(false, |f| call.starts_with(f));
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(call);
+        match failed {
+            true => Err(std::io::Error::new(std::io::ErrorKind::Other, "mock failure").into()),
+            false => {
+                change(&mut state);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl WindowOps for MockWindow {
+    fn monitors(&self) -> tauri::Result<Vec<MonitorInfo>> {
+        Ok(self.monitors.clone())
+    }
+
+    fn excluded_monitors(&self) -> tauri::Result<Vec<MonitorInfo>> {
+        Ok(Vec::new())
+    }
+
+    fn primary(&self) -> tauri::Result<Option<MonitorInfo>> {
+        Ok(self.monitors.first().cloned())
+    }
+
+    fn monitor(&self) -> tauri::Result<Option<MonitorInfo>> {
+        let position = self.position();
+        Ok(self
+            .monitors
+            .iter()
+            .find(|m| {
+                validation::contains_point(
+                    validation::Rect::new((m.x, m.y).into(), (m.width, m.height).into()),
+                    position.x as i64,
+                    position.y as i64,
+                )
+            })
+            .cloned())
+    }
+
+    fn logical_size(&self) -> tauri::Result<LogicalSize<f64>> {
+        Ok(self.size())
+    }
+
+    fn decorations_size(&self) -> tauri::Result<PhysicalSize<u32>> {
+        Ok(PhysicalSize::new(0, 0))
+    }
+
+    fn geometry(&self) -> tauri::Result<Geometry> {
+        let scale_factor = self.monitor()?.map_or(1., |m| m.scale_factor);
+        Ok(Geometry {
+            position: self.position(),
+            size: self.size().to_physical(scale_factor),
+        })
+    }
+
+    fn is_visible(&self) -> tauri::Result<bool> {
+        Ok(self.visible())
+    }
+
+    fn is_maximized(&self) -> tauri::Result<bool> {
+        Ok(self.maximized())
+    }
+
+    fn is_fullscreen(&self) -> tauri::Result<bool> {
+        Ok(self.fullscreen())
+    }
+
+    fn is_decorated(&self) -> tauri::Result<bool> {
+        Ok(self.decorated())
+    }
+
+    fn set_logical_size(&self, size: LogicalSize<f64>) -> tauri::Result<()> {
+        let call = format!("set_size {}x{}", size.width, size.height);
+        self.apply(call, |state| state.size = size)
+    }
+
+    fn set_physical_size(&self, size: PhysicalSize<u32>) -> tauri::Result<()> {
+        let scale_factor = self.monitor()?.map_or(1., |m| m.scale_factor);
+        self.set_logical_size(size.to_logical(scale_factor))
+    }
+
+    fn set_physical_position(&self, position: PhysicalPosition<i32>) -> tauri::Result<()> {
+        let call = format!("set_position {},{}", position.x, position.y);
+        self.apply(call, |state| state.position = position)
+    }
+
+    fn set_decorations(&self, decorated: bool) -> tauri::Result<()> {
+        let call = format!("set_decorations {}", decorated);
+        self.apply(call, |state| state.decorated = decorated)
+    }
+
+    fn set_fullscreen(&self, fullscreen: bool) -> tauri::Result<()> {
+        let call = format!("set_fullscreen {}", fullscreen);
+        self.apply(call, |state| state.fullscreen = fullscreen)
+    }
+
+    fn maximize(&self) -> tauri::Result<()> {
+        self.apply("maximize".into(), |state| state.maximized = true)
+    }
+
+    fn unmaximize(&self) -> tauri::Result<()> {
+        self.apply("unmaximize".into(), |state| state.maximized = false)
+    }
+
+    fn center(&self) -> tauri::Result<()> {
+        let monitor = self.monitor()?;
+        self.apply("center".into(), |state| {
+            if let Some(m) = monitor {
+                state.position = PhysicalPosition::new(
+                    m.x + (m.width as i32 - state.size.width as i32) / 2,
+                    m.y + (m.height as i32 - state.size.height as i32) / 2,
+                );
+            }
+        })
+    }
+
+    fn show(&self) -> tauri::Result<()> {
+        self.apply("show".into(), |state| state.visible = true)
+    }
+
+    fn set_focus(&self) -> tauri::Result<()> {
+        self.apply("set_focus".into(), |_| {})
+    }
+}
+
+/// Restores `state` with `flags` on `window` the way restoring a window with the default
+/// builder options does, returning the applied plan.
+///
+/// Warnings are logged, and the restore interceptor isn't called as it takes a real window.
+pub fn restore_geometry(
+    window: &MockWindow,
+    state: &WindowState,
+    flags: StateFlags,
+) -> tauri::Result<RestorePlan> {
+    let mut state = state.clone();
+    let options = RestoreOptions::default();
+    let mut plan = plan_geometry(window, &mut state, flags, options, None)?;
+    let warn = |warning: Warning| log::warn!("{:?}", warning);
+    apply_plan(window, "mock", &state, &mut plan, flags, options, &warn)?;
+    Ok(plan)
+}
+
+/// Replaces the stored state of all windows of `app`, which must run the plugin,
+/// so windows restored afterwards pick up `states` as if they were loaded from the state file.
+pub fn seed_state<R: Runtime, M: Manager<R>>(app: &M, states: HashMap<String, WindowState>) {
+    let mut loaded = PersistedState::from_windows(states);
+    sanitize_state(app, &mut loaded);
+    let app = app.app_handle();
+    let cache = app.state::<WindowStateCache>();
+    let mut cache = cache.0.lock().unwrap();
+    replace_state(&app, &mut cache, loaded);
+}
@@ -0,0 +1,213 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Geometry checks the plugin uses to decide whether a stored state can be restored,
+//! for apps placing their windows themselves.
+
+use serde::Serialize;
+use tauri::{Monitor, PhysicalPosition, PhysicalSize};
+
+/// A rectangle in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(position: PhysicalPosition<i32>, size: PhysicalSize<u32>) -> Self {
+        Self {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+        }
+    }
+
+    /// The area covered by `monitor`.
+    pub fn of_monitor(monitor: &Monitor) -> Self {
+        Self::new(*monitor.position(), *monitor.size())
+    }
+
+    fn right(&self) -> i64 {
+        self.x as i64 + self.width as i64
+    }
+
+    fn bottom(&self) -> i64 {
+        self.y as i64 + self.height as i64
+    }
+}
+
+/// Whether `rect` has a nonzero width and height.
+pub fn is_nonzero(rect: Rect) -> bool {
+    rect.width > 0 && rect.height > 0
+}
+
+/// Whether the point at `x` and `y` lies within `area`, excluding its right and bottom edges.
+pub fn contains_point(area: Rect, x: i64, y: i64) -> bool {
+    x >= area.x as i64 && x < area.right() && y >= area.y as i64 && y < area.bottom()
+}
+
+/// Whether any corner of `rect` lies within `area`, the check the plugin restores positions with.
+pub fn has_corner_in(rect: Rect, area: Rect) -> bool {
+    [
+        (rect.x as i64, rect.y as i64),
+        (rect.right(), rect.y as i64),
+        (rect.x as i64, rect.bottom()),
+        (rect.right(), rect.bottom()),
+    ]
+    .into_iter()
+    .any(|(x, y)| contains_point(area, x, y))
+}
+
+/// Whether `a` and `b` share any area, which empty rectangles never do.
+pub fn overlaps(a: Rect, b: Rect) -> bool {
+    is_nonzero(a)
+        && is_nonzero(b)
+        && (a.x as i64) < b.right()
+        && (b.x as i64) < a.right()
+        && (a.y as i64) < b.bottom()
+        && (b.y as i64) < a.bottom()
+}
+
+/// Whether any corner of `rect` lies on one of `monitors`.
+pub fn is_on_any_monitor(rect: Rect, monitors: &[Monitor]) -> bool {
+    monitors
+        .iter()
+        .any(|m| has_corner_in(rect, Rect::of_monitor(m)))
+}
+
+/// Whether a rectangle of `size` fits into `area`.
+pub fn fits(size: PhysicalSize<u32>, area: Rect) -> bool {
+    size.width <= area.width && size.height <= area.height
+}
+
+/// Shrinks `rect` to fit into `work_area`, then moves it just far enough to lie within it.
+pub fn clamp_to_work_area(rect: Rect, work_area: Rect) -> Rect {
+    let width = rect.width.min(work_area.width);
+    let height = rect.height.min(work_area.height);
+    let max_x = work_area.right() - width as i64;
+    let max_y = work_area.bottom() - height as i64;
+    Rect {
+        x: (rect.x as i64).clamp(work_area.x as i64, max_x) as i32,
+        y: (rect.y as i64).clamp(work_area.y as i64, max_y) as i32,
+        width,
+        height,
+    }
+}
+
+/// The fraction of `area` covered by `rect`, from `0.0` to `1.0`, `0.0` for an empty `area`.
+pub fn covered_fraction(rect: Rect, area: Rect) -> f64 {
+    let width = rect.right().min(area.right()) - (rect.x as i64).max(area.x as i64);
+    let height = rect.bottom().min(area.bottom()) - (rect.y as i64).max(area.y as i64);
+    if width <= 0 || height <= 0 || !is_nonzero(area) {
+        return 0.;
+    }
+    (width * height) as f64 / (area.width as f64 * area.height as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    const PRIMARY: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: 1920,
+        height: 1080,
+    };
+    /// A monitor left of [`PRIMARY`], at negative coordinates.
+    const LEFT: Rect = Rect {
+        x: -1280,
+        y: 0,
+        width: 1280,
+        height: 1024,
+    };
+
+    #[test]
+    fn negative_coordinates() {
+        assert!(contains_point(LEFT, -1280, 0));
+        assert!(!contains_point(LEFT, 0, 0));
+        assert!(has_corner_in(rect(-900, 100, 800, 600), LEFT));
+        assert!(!has_corner_in(rect(-900, 100, 800, 600), PRIMARY));
+        assert!(!overlaps(rect(-2000, -2000, 500, 500), LEFT));
+
+        let clamped = clamp_to_work_area(rect(-5000, -50, 800, 600), LEFT);
+        assert_eq!(clamped, rect(-1280, 0, 800, 600));
+    }
+
+    #[test]
+    fn rect_larger_than_every_monitor() {
+        let huge = rect(-2000, -100, 5000, 3000);
+        for monitor in [PRIMARY, LEFT] {
+            assert!(!fits(PhysicalSize::new(huge.width, huge.height), monitor));
+            // no corner lies on a monitor the rectangle encloses, although they share area
+            assert!(!has_corner_in(huge, monitor));
+            assert!(overlaps(huge, monitor));
+        }
+        assert_eq!(clamp_to_work_area(huge, PRIMARY), PRIMARY);
+    }
+
+    #[test]
+    fn zero_size_rect() {
+        let empty = rect(100, 100, 0, 0);
+        assert!(!is_nonzero(empty));
+        assert!(!is_nonzero(rect(100, 100, 800, 0)));
+        assert!(is_nonzero(rect(100, 100, 1, 1)));
+        assert!(!overlaps(empty, PRIMARY));
+        assert!(fits(PhysicalSize::new(0, 0), PRIMARY));
+        assert!(!contains_point(rect(0, 0, 0, 0), 0, 0));
+    }
+
+    #[test]
+    fn multi_monitor_intersection() {
+        // straddles the edge between the two monitors
+        let spanning = rect(-400, 200, 800, 600);
+        let monitors = [LEFT, PRIMARY];
+        assert!(monitors.iter().all(|m| overlaps(spanning, *m)));
+        assert!(monitors.iter().all(|m| has_corner_in(spanning, *m)));
+
+        // below the shorter left monitor, only the part on the primary one is visible
+        let low = rect(-400, 1030, 800, 40);
+        assert!(!overlaps(low, LEFT));
+        assert!(overlaps(low, PRIMARY));
+
+        // touching edges don't intersect
+        assert!(!overlaps(rect(-800, 0, 800, 600), PRIMARY));
+        assert!(!contains_point(LEFT, 0, 500));
+    }
+
+    #[test]
+    fn covered_fraction_around_a_threshold() {
+        // the example threshold of `Builder::with_maximize_detection_threshold`
+        const THRESHOLD: f64 = 0.95;
+        let monitor = rect(0, 0, 1000, 1000);
+
+        let below = covered_fraction(rect(0, 0, 1000, 949), monitor);
+        let at = covered_fraction(rect(0, 0, 1000, 950), monitor);
+        let above = covered_fraction(rect(0, 0, 1000, 951), monitor);
+        assert!(below < THRESHOLD);
+        assert!(at >= THRESHOLD);
+        assert!(above >= THRESHOLD);
+        assert_eq!(at, THRESHOLD);
+
+        // only the part on the monitor counts
+        let shifted = rect(-50, 0, 1000, 1000);
+        assert_eq!(covered_fraction(shifted, monitor), 0.95);
+        assert_eq!(covered_fraction(rect(-10, -10, 1020, 1020), monitor), 1.);
+        assert_eq!(covered_fraction(rect(1000, 0, 500, 500), monitor), 0.);
+        assert_eq!(covered_fraction(monitor, rect(0, 0, 0, 0)), 0.);
+    }
+}